@@ -1,5 +1,6 @@
 use sea_orm::sqlx::types::chrono;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::fs;
 use std::io::{Read, stdin};
 use std::path::Path;
@@ -17,12 +18,39 @@ pub static CONFIG_ENDPOINT_FONT: &str = "font";
 pub static CONFIG_ENDPOINT_FONT_UPLOAD: &str = "upload";
 pub static CONFIG_ENDPOINT_FONT_DOWNLOAD: &str = "download";
 pub static CONFIG_ENDPOINT_AUDIO_LEN: &str = "audio/len";
+pub static CONFIG_ENDPOINT_SOURCE_STATUS: &str = "source/status";
+pub static CONFIG_ENDPOINT_PRECACHE_STATUS: &str = "precache/status";
 
 static CONFIG_PATH: &str = "config.json5";
 #[derive(Debug, Deserialize, Serialize)]
 pub struct TosuConfig {
     pub url: String,
 }
+/// 请求歌词源时使用的网络配置
+#[derive(Debug, Default, Clone, Deserialize, Serialize)]
+pub struct SourceConfig {
+    /// 出站代理, 支持 `http://`, `https://`, `socks5://`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proxy: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub user_agent: Option<String>,
+    /// 按歌词源名称(`QQ`/`Netease`, 不区分大小写)附加的请求头
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub headers: HashMap<String, HashMap<String, String>>,
+    /// 按歌词源名称附加的 cookie, 例如 `"os=pc; appver=8.9.70"`
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub cookies: HashMap<String, String>,
+}
+
+impl SourceConfig {
+    fn is_empty(&self) -> bool {
+        self.proxy.is_none()
+            && self.user_agent.is_none()
+            && self.headers.is_empty()
+            && self.cookies.is_empty()
+    }
+}
+
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
     pub server: String,
@@ -31,6 +59,8 @@ pub struct Config {
     pub port: u16,
     pub database: String,
     pub tosu: Option<TosuConfig>,
+    #[serde(default, skip_serializing_if = "SourceConfig::is_empty")]
+    pub source: SourceConfig,
 }
 
 impl Default for Config {
@@ -43,6 +73,7 @@ impl Default for Config {
             tosu: Some(TosuConfig {
                 url: "ws://127.0.0.1:24050/websocket/v2".to_string(),
             }),
+            source: SourceConfig::default(),
         }
     }
 }
@@ -25,6 +25,28 @@ pub async fn init_database() {
     init_all_table().await.expect("can not create all table");
 }
 
+/// 测试用的临时数据库, 多个测试共用, 只初始化一次
+///
+/// 每个测试有自己的 runtime, 连接可能被重建, 所以不能用内存数据库
+#[cfg(test)]
+pub async fn init_test_database() {
+    static INIT: tokio::sync::OnceCell<()> = tokio::sync::OnceCell::const_new();
+    INIT.get_or_init(|| async {
+        let path = std::env::temp_dir().join(format!("tosu-proxy-test-{}.db", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let mut option = ConnectOptions::new(format!("sqlite://{}?mode=rwc", path.display()));
+        option
+            .max_connections(1)
+            .min_connections(1)
+            .sqlx_logging(false);
+        let connect = Database::connect(option).await.expect("无法创建测试数据库");
+        DATABASE_CONNECT.set(connect).expect("无法初始化数据库");
+        use super::entity::init_all_table;
+        init_all_table().await.expect("can not create all table");
+    })
+    .await;
+}
+
 pub fn database() -> &'static DatabaseConnection {
     DATABASE_CONNECT.get().expect("数据库连接池异常")
 }
@@ -55,3 +77,30 @@ pub async fn table_exists<T: AsRef<str>>(table_name: T) -> Result<bool> {
     let stmt = Statement::from_sql_and_values(backend, sql, vec![]);
     Ok(db.query_one(stmt).await?.is_some())
 }
+
+pub async fn column_names<T: AsRef<str>>(table_name: T) -> Result<Vec<String>> {
+    let table_name = table_name.as_ref();
+    let db = database();
+    let backend = db.get_database_backend();
+    let sql = match backend {
+        DbBackend::Sqlite => {
+            format!("SELECT name FROM pragma_table_info('{table_name}')")
+        }
+        DbBackend::Postgres => {
+            format!(
+                "SELECT column_name AS name FROM information_schema.columns WHERE table_schema='public' AND table_name='{table_name}'"
+            )
+        }
+        DbBackend::MySql => {
+            format!(
+                "SELECT column_name AS name FROM information_schema.columns WHERE table_schema = DATABASE() AND table_name = '{table_name}'"
+            )
+        }
+    };
+    let stmt = Statement::from_sql_and_values(backend, sql, vec![]);
+    let rows = db.query_all(stmt).await?;
+    Ok(rows
+        .iter()
+        .filter_map(|row| row.try_get::<String>("", "name").ok())
+        .collect())
+}
@@ -0,0 +1,70 @@
+use crate::database::database;
+use sea_orm::QueryOrder;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+
+/// 艺术家别名, 搜索歌词时用 `alias` 代替谱面的艺术家
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "artist_alias")]
+pub struct Model {
+    /// 谱面中的艺术家, 存小写, 匹配时忽略大小写
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub artist: String,
+    pub alias: String,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+    /// 匹配艺术家时比较的形式, 忽略首尾空白和大小写(包括非 ASCII 字符)
+    pub fn normalize(artist: &str) -> String {
+        artist.trim().to_lowercase()
+    }
+
+    /// 谱面记录的 `artists` 中有一个和 `normalized` 相同
+    pub fn matches(normalized: &str, artists: [Option<&str>; 2]) -> bool {
+        artists
+            .into_iter()
+            .flatten()
+            .any(|artist| Self::normalize(artist) == normalized)
+    }
+
+    pub async fn find_alias(artist: &str) -> crate::error::Result<Option<String>> {
+        Ok(Self::find_by_id(Self::normalize(artist))
+            .one(database())
+            .await?
+            .map(|model| model.alias))
+    }
+
+    pub async fn find_all() -> crate::error::Result<Vec<Model>> {
+        Ok(Self::find()
+            .order_by_asc(Column::Artist)
+            .all(database())
+            .await?)
+    }
+
+    pub async fn save(artist: &str, alias: &str) -> crate::error::Result<()> {
+        let model = ActiveModel::from(Model {
+            artist: Self::normalize(artist),
+            alias: alias.to_string(),
+        });
+        let mut on_conflict = OnConflict::column(Column::Artist);
+        on_conflict.update_column(Column::Alias);
+        Self::insert(model)
+            .on_conflict(on_conflict)
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    /// 返回是否删除了别名
+    pub async fn delete_alias(artist: &str) -> crate::error::Result<bool> {
+        let result = Self::delete_by_id(Self::normalize(artist))
+            .exec(database())
+            .await?;
+        Ok(result.rows_affected > 0)
+    }
+}
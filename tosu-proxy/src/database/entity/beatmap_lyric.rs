@@ -0,0 +1,206 @@
+use super::{artist_alias, lyric_cache};
+use crate::database::database;
+use crate::osu_source::OsuSongInfo;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{Expr, OnConflict, Query};
+use sea_orm::{QueryOrder, QuerySelect};
+
+/// 谱面使用的共享歌词, 见 `shared_lyric`
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "beatmap_lyric")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bid: i32,
+    #[sea_orm(indexed)]
+    pub sid: i32,
+    #[sea_orm(indexed)]
+    pub lyric_id: i32,
+    /// 列出缓存时显示, 旧版本的记录没有
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub artist_unicode: Option<String>,
+    /// 删除了这张谱面的缓存, 不再按音频使用这份歌词, 重新获取到歌词时恢复
+    #[sea_orm(default_value = false)]
+    pub detached: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+    pub async fn link(song: &OsuSongInfo, lyric_id: i32) -> crate::error::Result<()> {
+        let model = ActiveModel::from(Model {
+            bid: song.bid as i32,
+            sid: song.sid as i32,
+            lyric_id,
+            title: Some(song.title.clone()),
+            artist: Some(song.artist.clone()),
+            artist_unicode: Some(song.artist_unicode.clone()),
+            detached: false,
+        });
+        let mut on_conflict = OnConflict::column(Column::Bid);
+        on_conflict
+            .update_column(Column::Sid)
+            .update_column(Column::LyricId)
+            .update_column(Column::Title)
+            .update_column(Column::Artist)
+            .update_column(Column::ArtistUnicode)
+            .update_column(Column::Detached);
+        Self::insert(model)
+            .on_conflict(on_conflict)
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    /// 谱面在使用的共享歌词, 删除缓存后不再使用的不算
+    pub async fn find_by_bid(bid: i32) -> crate::error::Result<Option<Model>> {
+        Ok(Self::find_by_id(bid)
+            .filter(Column::Detached.eq(false))
+            .one(database())
+            .await?)
+    }
+
+    /// 是否删除缓存后不再使用 `lyric_id`
+    pub async fn is_detached(bid: i32, lyric_id: i32) -> crate::error::Result<bool> {
+        Ok(Self::find_by_id(bid)
+            .filter(Column::LyricId.eq(lyric_id))
+            .filter(Column::Detached.eq(true))
+            .one(database())
+            .await?
+            .is_some())
+    }
+
+    /// 谱面使用的共享歌词的 id, 传 `bid` 时只查一张谱面, 否则查整个谱面集
+    pub async fn find_lyric_ids(
+        bid: Option<i32>,
+        sid: Option<i32>,
+    ) -> crate::error::Result<Vec<i32>> {
+        let mut select = Self::find().filter(Column::Detached.eq(false));
+        if let Some(bid) = bid {
+            select = select.filter(Column::Bid.eq(bid));
+        } else if let Some(sid) = sid {
+            select = select.filter(Column::Sid.eq(sid));
+        } else {
+            return Ok(Vec::new());
+        }
+        Ok(select
+            .all(database())
+            .await?
+            .into_iter()
+            .map(|model| model.lyric_id)
+            .collect())
+    }
+
+    /// 艺术家或 Unicode 艺术家相同的谱面使用的共享歌词的 id,
+    /// 比较方式见 [`artist_alias::Entity::normalize`]
+    pub async fn find_lyric_ids_by_artist(artist: &str) -> crate::error::Result<Vec<i32>> {
+        let artist = artist_alias::Entity::normalize(artist);
+        Ok(Self::find()
+            .filter(Column::Detached.eq(false))
+            .all(database())
+            .await?
+            .into_iter()
+            .filter(|model| {
+                let artists = [model.artist.as_deref(), model.artist_unicode.as_deref()];
+                artist_alias::Entity::matches(&artist, artists)
+            })
+            .map(|model| model.lyric_id)
+            .collect())
+    }
+
+    /// 谱面(传 `bid` 时)或谱面集不再使用 `ids`, 返回修改的谱面
+    pub async fn detach(
+        bid: Option<i32>,
+        sid: Option<i32>,
+        ids: Vec<i32>,
+    ) -> crate::error::Result<Vec<i32>> {
+        let select = match (bid, sid) {
+            (Some(bid), _) => Self::find().filter(Column::Bid.eq(bid)),
+            (None, Some(sid)) => Self::find().filter(Column::Sid.eq(sid)),
+            (None, None) => return Ok(Vec::new()),
+        };
+        let bids = select
+            .filter(Column::LyricId.is_in(ids))
+            .filter(Column::Detached.eq(false))
+            .all(database())
+            .await?
+            .into_iter()
+            .map(|model| model.bid)
+            .collect::<Vec<_>>();
+        Self::update_many()
+            .col_expr(Column::Detached, Expr::value(true))
+            .filter(Column::Bid.is_in(bids.clone()))
+            .exec(database())
+            .await?;
+        Ok(bids)
+    }
+
+    /// 没有自己的缓存, 只通过共享歌词显示的谱面, 条件和
+    /// [`lyric_cache::Entity::find_by_title_like`] 相同, 按 bid 排序
+    ///
+    /// 从第 `offset` 条开始, 返回最多 `limit` 条和总数
+    pub async fn find_uncached(
+        title: Option<&str>,
+        artist: Option<&str>,
+        bid: Option<i32>,
+        offset: u64,
+        limit: u64,
+    ) -> crate::error::Result<(Vec<Model>, u64)> {
+        let cached = Query::select()
+            .column(lyric_cache::Column::Bid)
+            .from(lyric_cache::Entity)
+            .to_owned();
+        let mut select = Self::find()
+            .filter(Column::Detached.eq(false))
+            .filter(Column::Bid.not_in_subquery(cached));
+        if let Some(title) = title {
+            select = select.filter(Column::Title.contains(title));
+        }
+        if let Some(artist) = artist {
+            select = select.filter(Column::Artist.contains(artist));
+        }
+        if let Some(bid) = bid {
+            select = select.filter(Column::Bid.eq(bid));
+        }
+        let total = select.clone().count(database()).await?;
+        if limit == 0 {
+            return Ok((Vec::new(), total));
+        }
+        let models = select
+            .order_by_asc(Column::Bid)
+            .offset(offset)
+            .limit(limit)
+            .all(database())
+            .await?;
+        Ok((models, total))
+    }
+
+    /// `ids` 中没有谱面使用的
+    pub async fn filter_unused(ids: Vec<i32>) -> crate::error::Result<Vec<i32>> {
+        let used = Self::find()
+            .filter(Column::LyricId.is_in(ids.clone()))
+            .filter(Column::Detached.eq(false))
+            .all(database())
+            .await?
+            .into_iter()
+            .map(|model| model.lyric_id)
+            .collect::<Vec<_>>();
+        Ok(ids.into_iter().filter(|id| !used.contains(id)).collect())
+    }
+
+    pub async fn delete_by_lyric_ids(ids: Vec<i32>) -> crate::error::Result<()> {
+        Self::delete_many()
+            .filter(Column::LyricId.is_in(ids))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_all() -> crate::error::Result<()> {
+        Self::delete_many().exec(database()).await?;
+        Ok(())
+    }
+}
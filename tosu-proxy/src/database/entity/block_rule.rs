@@ -0,0 +1,78 @@
+use crate::database::database;
+use sea_orm::entity::prelude::*;
+use sea_orm::sqlx::types::chrono::Utc;
+use sea_orm::{ActiveValue, QueryOrder};
+
+/// 按范围拉黑, 见 `BlockScope`
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "block_rule")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// `bid`/`sid`/`title`/`artist`/`pattern`
+    pub scope: String,
+    pub value: String,
+    /// `false` 表示例外
+    pub block: bool,
+    /// 秒级时间戳
+    pub created_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+    pub async fn find_all() -> crate::error::Result<Vec<Model>> {
+        Ok(Self::find()
+            .order_by_asc(Column::Id)
+            .all(database())
+            .await?)
+    }
+
+    /// 同一范围同一个值只保留一条, 已存在时更新 `block`
+    pub async fn save(scope: &str, value: &str, block: bool) -> crate::error::Result<Model> {
+        let db = database();
+        let exist = Self::find()
+            .filter(Column::Scope.eq(scope))
+            .filter(Column::Value.eq(value))
+            .one(db)
+            .await?;
+        let model = match exist {
+            Some(model) => {
+                let mut model = ActiveModel::from(model);
+                model.block = ActiveValue::Set(block);
+                model.update(db).await?
+            }
+            None => {
+                let model = ActiveModel {
+                    id: ActiveValue::NotSet,
+                    scope: ActiveValue::Set(scope.to_string()),
+                    value: ActiveValue::Set(value.to_string()),
+                    block: ActiveValue::Set(block),
+                    created_at: ActiveValue::Set(Utc::now().timestamp()),
+                };
+                model.insert(db).await?
+            }
+        };
+        Ok(model)
+    }
+
+    /// 返回是否删除了规则
+    pub async fn delete_rule(id: i32) -> crate::error::Result<bool> {
+        let result = Self::delete_by_id(id).exec(database()).await?;
+        Ok(result.rows_affected > 0)
+    }
+
+    /// 删除例外规则, 拉黑规则保留
+    pub async fn delete_exception(scope: &str, value: &str) -> crate::error::Result<()> {
+        Self::delete_many()
+            .filter(Column::Scope.eq(scope))
+            .filter(Column::Value.eq(value))
+            .filter(Column::Block.eq(false))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+}
@@ -1,9 +1,12 @@
+use super::artist_alias;
 use crate::database::database;
 use crate::database::entity::DB_ERROR_MESSAGE;
-use crate::lyric::Lyric;
-use sea_orm::ActiveValue;
+use crate::lyric::{Lyric, LyricOrigin};
+use crate::osu_source::OsuSongInfo;
+use sea_orm::{ActiveValue, QueryOrder, QuerySelect};
 use sea_orm::entity::prelude::*;
-use sea_orm::sea_query::OnConflict;
+use sea_orm::sea_query::{Expr, OnConflict, SimpleExpr};
+use sea_orm::sqlx::types::chrono::Utc;
 
 #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
 #[sea_orm(table_name = "lyric_cache")]
@@ -14,8 +17,25 @@ pub struct Model {
     #[sea_orm(column_type = "Blob")]
     pub cache: Vec<u8>,
     pub title: String,
+    /// 旧版本的缓存没有记录
+    pub artist: Option<String>,
+    pub artist_unicode: Option<String>,
     /// ms
     pub audio_length: i32,
+    /// 歌词来源, 见 [`LyricOrigin`]
+    pub source: Option<String>,
+    pub source_key: Option<String>,
+    /// 翻译来源, 与歌词来源不同时说明是合并来的
+    pub trans_source: Option<String>,
+    pub trans_source_key: Option<String>,
+    /// 获取歌词的时间, 秒级时间戳, 旧版本的缓存没有
+    pub fetched_at: Option<i64>,
+    /// 是否为手动选择的歌词
+    #[sea_orm(default_value = false)]
+    pub manual: bool,
+    /// 固定的缓存不会过期, 也不会被批量清除, 手动选择时自动固定
+    #[sea_orm(default_value = false)]
+    pub pinned: bool,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -38,11 +58,32 @@ impl Entity {
             .await?)
     }
 
-    pub async fn find_by_title_like(title: &str) -> crate::error::Result<Vec<Model>> {
-        Ok(Self::find()
-            .filter(Column::Title.contains(title))
-            .all(database())
-            .await?)
+    /// 标题/艺术家模糊匹配, bid 精确匹配, 为 `None` 的条件不过滤
+    ///
+    /// 最近获取的在前, `page` 从 0 开始, 返回当前页和总数
+    pub async fn find_by_title_like(
+        title: Option<&str>,
+        artist: Option<&str>,
+        bid: Option<i32>,
+        page: u64,
+        limit: u64,
+    ) -> crate::error::Result<(Vec<Model>, u64)> {
+        let mut select = Self::find();
+        if let Some(title) = title {
+            select = select.filter(Column::Title.contains(title));
+        }
+        if let Some(artist) = artist {
+            select = select.filter(Column::Artist.contains(artist));
+        }
+        if let Some(bid) = bid {
+            select = select.filter(Column::Bid.eq(bid));
+        }
+        let paginator = select
+            .order_by_desc(Column::FetchedAt)
+            .order_by_asc(Column::Bid)
+            .paginate(database(), limit);
+        let total = paginator.num_items().await?;
+        Ok((paginator.fetch_page(page).await?, total))
     }
 
     pub async fn all_count() -> crate::error::Result<u64> {
@@ -51,6 +92,34 @@ impl Entity {
             .await?)
     }
 
+    /// 返回删除的谱面, `force` 为 `false` 时跳过固定的缓存
+    pub async fn delete_by_bid(bid: i32, force: bool) -> crate::error::Result<Vec<i32>> {
+        Self::delete_matching(Column::Bid.eq(bid), force).await
+    }
+
+    /// 删除整个谱面集, 返回删除的谱面, `force` 为 `false` 时跳过固定的缓存
+    pub async fn delete_by_sid(sid: i32, force: bool) -> crate::error::Result<Vec<i32>> {
+        Self::delete_matching(Column::Sid.eq(sid), force).await
+    }
+
+    async fn delete_matching(condition: SimpleExpr, force: bool) -> crate::error::Result<Vec<i32>> {
+        let mut select = Self::find().filter(condition);
+        if !force {
+            select = select.filter(Column::Pinned.eq(false));
+        }
+        let bids = select
+            .all(database())
+            .await?
+            .into_iter()
+            .map(|model| model.bid)
+            .collect::<Vec<_>>();
+        Self::delete_many()
+            .filter(Column::Bid.is_in(bids.clone()))
+            .exec(database())
+            .await?;
+        Ok(bids)
+    }
+
     pub async fn delete_all() -> crate::error::Result<()> {
         Self::delete_many()
             .exec(database())
@@ -58,27 +127,109 @@ impl Entity {
         Ok(())
     }
 
-    /// - `sid`：sid
-    /// - `bid`：bid
-    /// - `title`：title
-    /// - `audio_length`：毫秒
+    /// 删除谱面(`bid` 不为 `None` 时)或谱面集自动获取的缓存, 手动选择和固定的保留
+    pub async fn delete_auto(bid: Option<i32>, sid: i32) -> crate::error::Result<u64> {
+        let delete = match bid {
+            Some(bid) => Self::delete_many().filter(Column::Bid.eq(bid)),
+            None => Self::delete_many().filter(Column::Sid.eq(sid)),
+        };
+        Ok(delete
+            .filter(Column::Manual.eq(false))
+            .filter(Column::Pinned.eq(false))
+            .exec(database())
+            .await?
+            .rows_affected)
+    }
+
+    /// 删除艺术家或 Unicode 艺术家相同的自动获取的缓存, 手动选择和固定的保留,
+    /// 比较方式见 [`artist_alias::Entity::normalize`]
+    pub async fn delete_auto_by_artist(artist: &str) -> crate::error::Result<u64> {
+        let artist = artist_alias::Entity::normalize(artist);
+        let bids = Self::find()
+            .select_only()
+            .columns([Column::Bid, Column::Artist, Column::ArtistUnicode])
+            .filter(Column::Manual.eq(false))
+            .filter(Column::Pinned.eq(false))
+            .into_tuple::<(i32, Option<String>, Option<String>)>()
+            .all(database())
+            .await?
+            .into_iter()
+            .filter(|(_, romanized, unicode)| {
+                artist_alias::Entity::matches(&artist, [romanized.as_deref(), unicode.as_deref()])
+            })
+            .map(|(bid, _, _)| bid)
+            .collect::<Vec<_>>();
+        Ok(Self::delete_many()
+            .filter(Column::Bid.is_in(bids))
+            .exec(database())
+            .await?
+            .rows_affected)
+    }
+
+    /// 清除缓存时保留固定的
+    pub async fn delete_unpinned() -> crate::error::Result<()> {
+        Self::delete_many()
+            .filter(Column::Pinned.eq(false))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    /// 返回修改的数量
+    pub async fn set_pinned(bid: i32, pinned: bool) -> crate::error::Result<u64> {
+        let result = Self::update_many()
+            .col_expr(Column::Pinned, Expr::value(pinned))
+            .filter(Column::Bid.eq(bid))
+            .exec(database())
+            .await?;
+        Ok(result.rows_affected)
+    }
+
+    /// - `song`：谱面, 记录 bid/sid/标题/艺术家/时长
     /// - `lyric`：歌词
+    /// - `origin`：歌词来源, 不知道时为 `None`
+    /// - `manual`：是否为手动选择的歌词, 手动选择时固定缓存
     pub async fn save(
-        sid: i32,
-        bid: i32,
-        title: &str,
-        audio_length: i32,
+        song: &OsuSongInfo,
         lyric: &Lyric,
+        origin: Option<&LyricOrigin>,
+        manual: bool,
     ) -> crate::error::Result<()> {
+        let source = origin.map(|origin| &origin.lyric);
+        let trans = origin.and_then(|origin| origin.trans.as_ref());
         let model = ActiveModel {
-            sid: ActiveValue::Set(sid),
-            bid: ActiveValue::Set(bid),
-            title: ActiveValue::Set(title.to_string()),
-            audio_length: ActiveValue::Set(audio_length),
+            sid: ActiveValue::Set(song.sid as i32),
+            bid: ActiveValue::Set(song.bid as i32),
+            title: ActiveValue::Set(song.title.clone()),
+            artist: ActiveValue::Set(Some(song.artist.clone())),
+            artist_unicode: ActiveValue::Set(Some(song.artist_unicode.clone())),
+            audio_length: ActiveValue::Set(song.length),
             cache: ActiveValue::Set(lyric.to_json_cache()?),
+            source: ActiveValue::Set(source.map(|key| key.source_type.clone())),
+            source_key: ActiveValue::Set(source.map(|key| key.key.clone())),
+            trans_source: ActiveValue::Set(trans.map(|key| key.source_type.clone())),
+            trans_source_key: ActiveValue::Set(trans.map(|key| key.key.clone())),
+            fetched_at: ActiveValue::Set(Some(Utc::now().timestamp())),
+            manual: ActiveValue::Set(manual),
+            pinned: ActiveValue::Set(manual),
         };
 
-        Self::save_model(model).await
+        // 冲突更新时保留原来的固定状态
+        Self::save_model(model).await?;
+        if manual {
+            Self::set_pinned(song.bid as i32, true).await?;
+        }
+        Ok(())
+    }
+
+    /// 重新搜索后保留旧歌词时, 只更新获取时间
+    pub async fn set_fetched_at(bid: i32, fetched_at: i64) -> crate::error::Result<()> {
+        Self::update_many()
+            .col_expr(Column::FetchedAt, Expr::value(fetched_at))
+            .filter(Column::Bid.eq(bid))
+            .exec(database())
+            .await?;
+        Ok(())
     }
 
     pub async fn save_model(model: ActiveModel) -> crate::error::Result<()> {
@@ -88,7 +239,15 @@ impl Entity {
             .update_column(Column::Sid)
             .update_column(Column::Cache)
             .update_column(Column::Title)
-            .update_column(Column::AudioLength);
+            .update_column(Column::Artist)
+            .update_column(Column::ArtistUnicode)
+            .update_column(Column::AudioLength)
+            .update_column(Column::Source)
+            .update_column(Column::SourceKey)
+            .update_column(Column::TransSource)
+            .update_column(Column::TransSourceKey)
+            .update_column(Column::FetchedAt)
+            .update_column(Column::Manual);
 
         Self::insert(model)
             .on_conflict(on_conflict)
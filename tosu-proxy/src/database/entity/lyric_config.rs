@@ -2,9 +2,9 @@ use crate::database::database;
 use crate::database::entity::DB_ERROR_MESSAGE;
 use sea_orm::entity::prelude::*;
 use sea_orm::sea_query::OnConflict;
-use sea_orm::{ColumnTrait, QueryFilter};
+use sea_orm::{ColumnTrait, QueryFilter, QueryOrder};
 
-#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel)]
 #[sea_orm(table_name = "lyric_config")]
 pub struct Model {
     #[sea_orm(primary_key, auto_increment = false)]
@@ -15,6 +15,11 @@ pub struct Model {
     pub title: String,
     pub disable: bool,
     pub offset: i32,
+    /// 歌词时间 = 播放时间 * scale + offset, 为空时是 1
+    pub scale: Option<f64>,
+    /// 最近一次设置的同步点, 毫秒
+    pub anchor_play_time: Option<i32>,
+    pub anchor_lyric_time: Option<i32>,
 }
 
 #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
@@ -39,32 +44,81 @@ impl Entity {
             return Ok(Some(model));
         }
 
-        // bid 未找到, 再找 title
-        if let Some(model) = Self::find()
-            .filter(Column::Title.eq(title))
-            .one(db)
-            .await?
+        // bid 未找到, 再找 title; 旧版本只按 bid 保存的设置没有标题和 sid, 不参与匹配
+        if !title.is_empty()
+            && let Some(model) = Self::find()
+                .filter(Column::Title.eq(title))
+                .one(db)
+                .await?
         {
             return Ok(Some(model));
         }
 
         // 都未找到，根据 sid 查询第一个
-        if let Some(model) = Self::find()
-            .filter(Column::Sid.eq(sid))
-            .one(db)
-            .await?
+        if sid > 0
+            && let Some(model) = Self::find()
+                .filter(Column::Sid.eq(sid))
+                .one(db)
+                .await?
         {
             return Ok(Some(model));
         }
         Ok(None)
     }
-    pub async fn find_setting(bid: i32, sid: i32, title: &str) -> crate::error::Result<(bool, i32)> {
+    /// 偏移按 bid/title/sid 的顺序查找, 同一首歌的其他难度共用;
+    /// 拉黑只看 bid, 见 `block_service`
+    pub async fn find_offset(bid: i32, sid: i32, title: &str) -> crate::error::Result<i32> {
         if let Some(m) = Entity::find_first(bid, sid, title).await? {
-            Ok((m.disable, m.offset))
+            Ok(m.offset)
         } else {
             // 都没找到，返回默认值 0
-            Ok((false, 0))
+            Ok(0)
+        }
+    }
+
+    /// 返回偏移和倍率, 偏移和 [`Self::find_offset`] 相同的查找顺序;
+    /// 倍率和同步点一样只看 bid, 其他难度没有算出它的同步点
+    pub async fn find_timing(bid: i32, sid: i32, title: &str) -> crate::error::Result<(i32, f64)> {
+        if let Some(m) = Self::find_by_bid(bid).await? {
+            return Ok((m.offset, m.scale.unwrap_or(1.0)));
         }
+        Ok((Self::find_offset(bid, sid, title).await?, 1.0))
+    }
+
+    /// 保存偏移、倍率和同步点, 不修改拉黑状态
+    pub async fn save_sync(
+        bid: i32,
+        sid: i32,
+        title: &str,
+        offset: i32,
+        scale: Option<f64>,
+        anchor: Option<(i32, i32)>,
+    ) -> crate::error::Result<()> {
+        let model = ActiveModel::from(Model {
+            bid,
+            sid,
+            title: title.to_string(),
+            disable: false,
+            offset,
+            scale,
+            anchor_play_time: anchor.map(|(play, _)| play),
+            anchor_lyric_time: anchor.map(|(_, lyric)| lyric),
+        });
+
+        let mut on_conflict = OnConflict::column(Column::Bid);
+        on_conflict
+            .update_column(Column::Sid)
+            .update_column(Column::Title)
+            .update_column(Column::Offset)
+            .update_column(Column::Scale)
+            .update_column(Column::AnchorPlayTime)
+            .update_column(Column::AnchorLyricTime);
+
+        Self::insert(model)
+            .on_conflict(on_conflict)
+            .exec(database())
+            .await?;
+        Ok(())
     }
 
     pub async fn save_setting(bid: i32, sid: i32, title: &str, block: bool, offset: i32) -> crate::error::Result<()> {
@@ -74,6 +128,9 @@ impl Entity {
             title: title.to_string(),
             disable: block,
             offset,
+            scale: None,
+            anchor_play_time: None,
+            anchor_lyric_time: None,
         });
 
         let mut on_conflict = OnConflict::column(Column::Bid);
@@ -90,6 +147,20 @@ impl Entity {
         Ok(())
     }
 
+    pub async fn find_by_bid(bid: i32) -> crate::error::Result<Option<Model>> {
+        Ok(Self::find_by_id(bid).one(database()).await?)
+    }
+
+    /// 设置了偏移或倍率的谱面, `page` 从 0 开始, 返回当前页和总数
+    pub async fn find_offsets(page: u64, limit: u64) -> crate::error::Result<(Vec<Model>, u64)> {
+        let paginator = Self::find()
+            .filter(Column::Offset.ne(0).or(Column::Scale.is_not_null()))
+            .order_by_asc(Column::Bid)
+            .paginate(database(), limit);
+        let total = paginator.num_items().await?;
+        Ok((paginator.fetch_page(page).await?, total))
+    }
+
     pub async fn delete_by_bid(bid: i32) -> crate::error::Result<()> {
         Self::delete_by_id(bid)
             .exec(database())
@@ -97,6 +168,19 @@ impl Entity {
         Ok(())
     }
 
+    /// 所有设置都是默认值时删除这一行
+    pub async fn delete_if_default(bid: i32) -> crate::error::Result<()> {
+        Self::delete_many()
+            .filter(Column::Bid.eq(bid))
+            .filter(Column::Disable.eq(false))
+            .filter(Column::Offset.eq(0))
+            .filter(Column::Scale.is_null())
+            .filter(Column::AnchorPlayTime.is_null())
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
     pub async fn get_all_disable() -> crate::error::Result<Vec<(i32, i32, String)>> {
         Ok(Self::find()
             .filter(Column::Disable.eq(true))
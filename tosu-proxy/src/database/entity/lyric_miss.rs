@@ -0,0 +1,121 @@
+use super::artist_alias;
+use crate::database::database;
+use crate::osu_source::OsuSongInfo;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::OnConflict;
+use sea_orm::sqlx::types::chrono::Utc;
+use sea_orm::{QueryOrder, QuerySelect};
+
+/// 没有找到歌词的记录, 有效期内切到这张谱面不再请求歌词源
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "lyric_miss")]
+pub struct Model {
+    #[sea_orm(primary_key, auto_increment = false)]
+    pub bid: i32,
+    #[sea_orm(indexed)]
+    pub sid: i32,
+    pub title: String,
+    /// 旧版本的记录没有
+    pub artist: Option<String>,
+    pub artist_unicode: Option<String>,
+    /// 歌词源标记为纯音乐
+    pub instrumental: bool,
+    /// 搜索时间, 秒级时间戳
+    pub checked_at: i64,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+    /// 先按 bid 再按 sid 查找 `since` 之后的记录
+    pub async fn find_valid(bid: i32, sid: i32, since: i64) -> crate::error::Result<Option<Model>> {
+        let db = database();
+        let valid = Column::CheckedAt.gte(since);
+        if let Some(model) = Self::find_by_id(bid).filter(valid.clone()).one(db).await? {
+            return Ok(Some(model));
+        }
+        Ok(Self::find()
+            .filter(Column::Sid.eq(sid))
+            .filter(valid)
+            .one(db)
+            .await?)
+    }
+
+    pub async fn save(song: &OsuSongInfo, instrumental: bool) -> crate::error::Result<()> {
+        let model = ActiveModel::from(Model {
+            bid: song.bid as i32,
+            sid: song.sid as i32,
+            title: song.title_unicode.clone(),
+            artist: Some(song.artist.clone()),
+            artist_unicode: Some(song.artist_unicode.clone()),
+            instrumental,
+            checked_at: Utc::now().timestamp(),
+        });
+
+        let mut on_conflict = OnConflict::column(Column::Bid);
+        on_conflict
+            .update_column(Column::Sid)
+            .update_column(Column::Title)
+            .update_column(Column::Artist)
+            .update_column(Column::ArtistUnicode)
+            .update_column(Column::Instrumental)
+            .update_column(Column::CheckedAt);
+
+        Self::insert(model)
+            .on_conflict(on_conflict)
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    /// 最近的记录在前
+    pub async fn find_all() -> crate::error::Result<Vec<Model>> {
+        Ok(Self::find()
+            .order_by_desc(Column::CheckedAt)
+            .all(database())
+            .await?)
+    }
+
+    pub async fn delete_by_bid(bid: i32) -> crate::error::Result<()> {
+        Self::delete_by_id(bid).exec(database()).await?;
+        Ok(())
+    }
+
+    pub async fn delete_by_sid(sid: i32) -> crate::error::Result<()> {
+        Self::delete_many()
+            .filter(Column::Sid.eq(sid))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    /// 艺术家或 Unicode 艺术家相同, 比较方式见 [`artist_alias::Entity::normalize`]
+    pub async fn delete_by_artist(artist: &str) -> crate::error::Result<()> {
+        let artist = artist_alias::Entity::normalize(artist);
+        let bids = Self::find()
+            .select_only()
+            .columns([Column::Bid, Column::Artist, Column::ArtistUnicode])
+            .into_tuple::<(i32, Option<String>, Option<String>)>()
+            .all(database())
+            .await?
+            .into_iter()
+            .filter(|(_, romanized, unicode)| {
+                artist_alias::Entity::matches(&artist, [romanized.as_deref(), unicode.as_deref()])
+            })
+            .map(|(bid, _, _)| bid)
+            .collect::<Vec<_>>();
+        Self::delete_many()
+            .filter(Column::Bid.is_in(bids))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_all() -> crate::error::Result<()> {
+        Self::delete_many().exec(database()).await?;
+        Ok(())
+    }
+}
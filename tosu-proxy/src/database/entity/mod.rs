@@ -1,15 +1,34 @@
+mod artist_alias;
+mod beatmap_lyric;
+mod block_rule;
 mod lyric_cache;
 mod lyric_config;
+mod lyric_miss;
+mod search_override;
 mod setting;
+mod shared_lyric;
 
-use crate::database::table_exists;
+use crate::database::{column_names, table_exists};
 use crate::error::Result;
-use sea_orm::{ConnectionTrait, EntityName, Schema};
+use sea_orm::sea_query::Table;
+use sea_orm::{ConnectionTrait, EntityName, EntityTrait, IdenStatic, Iterable, Schema};
+use tracing::info;
 
 use super::database;
+pub use artist_alias::Entity as ArtistAliasEntity;
+pub use artist_alias::Model as ArtistAliasModel;
+pub use beatmap_lyric::Entity as BeatmapLyricEntity;
+pub use beatmap_lyric::Model as BeatmapLyricModel;
+pub use block_rule::Entity as BlockRuleEntity;
 pub use lyric_cache::Entity as LyricCacheEntity;
+pub use lyric_cache::Model as LyricCacheModel;
 pub use lyric_config::Entity as LyricConfigEntity;
+pub use lyric_miss::Entity as LyricMissEntity;
+pub use search_override::Entity as SearchOverrideEntity;
+pub use search_override::Model as SearchOverrideModel;
 pub use setting::Entity as SettingEntity;
+pub use shared_lyric::Entity as SharedLyricEntity;
+pub use shared_lyric::Model as SharedLyricModel;
 
 static DB_ERROR_MESSAGE: &str = "无法查询数据库";
 
@@ -29,14 +48,98 @@ macro_rules! init_entity {
                 let schema = Schema::new(backend)
                     .create_table_from_entity($entity);
                 db.execute(backend.build(&schema)).await?;
+            } else {
+                add_missing_columns($entity).await?;
             }
             Ok(())
         }
         )*
     };
 }
+
+/// 旧版本建的表缺少新加的列时补上, 因此新加的列必须可为空或者有默认值
+async fn add_missing_columns<E: EntityTrait>(entity: E) -> Result<()> {
+    let table_name = entity.table_name();
+    let exists = column_names(table_name).await?;
+    let db = database();
+    let backend = db.get_database_backend();
+    let schema = Schema::new(backend);
+    for column in E::Column::iter() {
+        let name = column.as_str();
+        if exists.iter().any(|exist| exist.eq_ignore_ascii_case(name)) {
+            continue;
+        }
+        let statement = Table::alter()
+            .table(entity)
+            .add_column(schema.get_column_def::<E>(column))
+            .to_owned();
+        db.execute(backend.build(&statement)).await?;
+        info!("数据表 {table_name} 新增列 {name}");
+    }
+    Ok(())
+}
 init_entity! {
     init_setting(SettingEntity),
     init_lyric_cache(LyricCacheEntity),
     init_lyric_config(LyricConfigEntity),
+    init_lyric_miss(LyricMissEntity),
+    init_shared_lyric(SharedLyricEntity),
+    init_beatmap_lyric(BeatmapLyricEntity),
+    init_block_rule(BlockRuleEntity),
+    init_search_override(SearchOverrideEntity),
+    init_artist_alias(ArtistAliasEntity),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_test_database;
+
+    /// 模拟旧版本建的表, 后来加了 `fetched_at` 和 `manual`
+    mod upgrade {
+        use sea_orm::entity::prelude::*;
+
+        #[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+        #[sea_orm(table_name = "upgrade_test")]
+        pub struct Model {
+            #[sea_orm(primary_key, auto_increment = false)]
+            pub bid: i32,
+            pub title: String,
+            pub fetched_at: Option<i64>,
+            #[sea_orm(default_value = false)]
+            pub manual: bool,
+        }
+
+        #[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+        pub enum Relation {}
+
+        impl ActiveModelBehavior for ActiveModel {}
+    }
+
+    #[tokio::test]
+    async fn test_add_missing_columns() -> Result<()> {
+        init_test_database().await;
+        let db = database();
+        db.execute_unprepared("DROP TABLE IF EXISTS upgrade_test")
+            .await?;
+        db.execute_unprepared(
+            "CREATE TABLE upgrade_test (bid INTEGER PRIMARY KEY, title TEXT NOT NULL)",
+        )
+        .await?;
+        db.execute_unprepared("INSERT INTO upgrade_test (bid, title) VALUES (1, 'old')")
+            .await?;
+
+        add_missing_columns(upgrade::Entity).await?;
+
+        let columns = column_names("upgrade_test").await?;
+        assert!(columns.iter().any(|column| column == "manual"));
+        let model = upgrade::Entity::find_by_id(1)
+            .one(db)
+            .await?
+            .ok_or("row lost")?;
+        assert_eq!(model.title, "old");
+        assert_eq!(model.fetched_at, None);
+        assert!(!model.manual);
+        Ok(())
+    }
 }
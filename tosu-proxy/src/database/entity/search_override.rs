@@ -0,0 +1,93 @@
+use crate::database::database;
+use sea_orm::entity::prelude::*;
+use sea_orm::{ActiveValue, QueryOrder};
+
+/// 搜索歌词时替换谱面的标题/艺术家, `bid` 为 0 时对整个谱面集生效
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "search_override")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    #[sea_orm(indexed)]
+    pub bid: i32,
+    #[sea_orm(indexed)]
+    pub sid: i32,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+    /// 先按 bid 再按谱面集查找
+    pub async fn find_for(bid: i32, sid: i32) -> crate::error::Result<Option<Model>> {
+        let db = database();
+        if bid > 0
+            && let Some(model) = Self::find().filter(Column::Bid.eq(bid)).one(db).await?
+        {
+            return Ok(Some(model));
+        }
+        if sid <= 0 {
+            return Ok(None);
+        }
+        Ok(Self::find()
+            .filter(Column::Bid.eq(0))
+            .filter(Column::Sid.eq(sid))
+            .one(db)
+            .await?)
+    }
+
+    pub async fn find_all() -> crate::error::Result<Vec<Model>> {
+        Ok(Self::find()
+            .order_by_asc(Column::Sid)
+            .order_by_asc(Column::Bid)
+            .all(database())
+            .await?)
+    }
+
+    /// 同一张谱面(或谱面集)只保留一条, 谱面按 bid 去重, 谱面集按 sid 去重
+    pub async fn save(
+        bid: i32,
+        sid: i32,
+        title: Option<&str>,
+        artist: Option<&str>,
+    ) -> crate::error::Result<Model> {
+        let db = database();
+        let mut exist = Self::find().filter(Column::Bid.eq(bid));
+        if bid == 0 {
+            exist = exist.filter(Column::Sid.eq(sid));
+        }
+        let exist = exist.one(db).await?;
+        let title = ActiveValue::Set(title.map(str::to_string));
+        let artist = ActiveValue::Set(artist.map(str::to_string));
+        let model = match exist {
+            Some(model) => {
+                let mut model = ActiveModel::from(model);
+                model.sid = ActiveValue::Set(sid);
+                model.title = title;
+                model.artist = artist;
+                model.update(db).await?
+            }
+            None => {
+                let model = ActiveModel {
+                    id: ActiveValue::NotSet,
+                    bid: ActiveValue::Set(bid),
+                    sid: ActiveValue::Set(sid),
+                    title,
+                    artist,
+                };
+                model.insert(db).await?
+            }
+        };
+        Ok(model)
+    }
+
+    /// 返回是否删除了记录
+    pub async fn delete_override(id: i32) -> crate::error::Result<bool> {
+        let result = Self::delete_by_id(id).exec(database()).await?;
+        Ok(result.rows_affected > 0)
+    }
+}
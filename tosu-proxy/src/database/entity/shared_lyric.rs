@@ -0,0 +1,174 @@
+use crate::database::database;
+use crate::lyric::{Lyric, LyricOrigin};
+use sea_orm::ActiveValue;
+use sea_orm::entity::prelude::*;
+use sea_orm::sea_query::{Expr, OnConflict};
+use sea_orm::sqlx::types::chrono::Utc;
+
+/// 按音频内容去重的歌词, 相同音频的谱面通过 `beatmap_lyric` 共用一份
+#[derive(Clone, Debug, PartialEq, DeriveEntityModel, Eq)]
+#[sea_orm(table_name = "shared_lyric")]
+pub struct Model {
+    #[sea_orm(primary_key)]
+    pub id: i32,
+    /// 音频文件内容的 SHA-256
+    #[sea_orm(unique)]
+    pub audio_hash: String,
+    #[sea_orm(column_type = "Blob")]
+    pub cache: Vec<u8>,
+    pub source: Option<String>,
+    pub source_key: Option<String>,
+    pub trans_source: Option<String>,
+    pub trans_source_key: Option<String>,
+    /// 获取歌词的时间, 秒级时间戳
+    pub fetched_at: Option<i64>,
+    /// 是否为手动选择的歌词
+    #[sea_orm(default_value = false)]
+    pub manual: bool,
+    /// 跟随用到它的谱面的缓存一起固定
+    #[sea_orm(default_value = false)]
+    pub pinned: bool,
+}
+
+#[derive(Copy, Clone, Debug, EnumIter, DeriveRelation)]
+pub enum Relation {}
+
+impl ActiveModelBehavior for ActiveModel {}
+
+impl Entity {
+    pub async fn find_by_hash(audio_hash: &str) -> crate::error::Result<Option<Model>> {
+        Ok(Self::find()
+            .filter(Column::AudioHash.eq(audio_hash))
+            .one(database())
+            .await?)
+    }
+
+    pub async fn find_by_ids(ids: Vec<i32>) -> crate::error::Result<Vec<Model>> {
+        Ok(Self::find()
+            .filter(Column::Id.is_in(ids))
+            .all(database())
+            .await?)
+    }
+
+    /// 相同音频已有歌词时覆盖, 返回歌词的 id
+    pub async fn save(
+        audio_hash: &str,
+        lyric: &Lyric,
+        origin: Option<&LyricOrigin>,
+        manual: bool,
+    ) -> crate::error::Result<i32> {
+        let source = origin.map(|origin| &origin.lyric);
+        let trans = origin.and_then(|origin| origin.trans.as_ref());
+        let model = ActiveModel {
+            id: ActiveValue::NotSet,
+            audio_hash: ActiveValue::Set(audio_hash.to_string()),
+            cache: ActiveValue::Set(lyric.to_json_cache()?),
+            source: ActiveValue::Set(source.map(|key| key.source_type.clone())),
+            source_key: ActiveValue::Set(source.map(|key| key.key.clone())),
+            trans_source: ActiveValue::Set(trans.map(|key| key.source_type.clone())),
+            trans_source_key: ActiveValue::Set(trans.map(|key| key.key.clone())),
+            fetched_at: ActiveValue::Set(Some(Utc::now().timestamp())),
+            manual: ActiveValue::Set(manual),
+            pinned: ActiveValue::Set(manual),
+        };
+
+        let mut on_conflict = OnConflict::column(Column::AudioHash);
+        on_conflict
+            .update_column(Column::Cache)
+            .update_column(Column::Source)
+            .update_column(Column::SourceKey)
+            .update_column(Column::TransSource)
+            .update_column(Column::TransSourceKey)
+            .update_column(Column::FetchedAt)
+            .update_column(Column::Manual);
+        Self::insert(model)
+            .on_conflict(on_conflict)
+            .exec(database())
+            .await?;
+
+        // 冲突更新时各数据库返回的 id 不一致, 重新查一次
+        let model = Self::find_by_hash(audio_hash)
+            .await?
+            .ok_or("共享歌词保存失败")?;
+        if manual && !model.pinned {
+            Self::set_pinned(vec![model.id], true).await?;
+        }
+        Ok(model.id)
+    }
+
+    /// 重新搜索后保留旧歌词时, 只更新获取时间
+    pub async fn set_fetched_at(id: i32, fetched_at: i64) -> crate::error::Result<()> {
+        Self::update_many()
+            .col_expr(Column::FetchedAt, Expr::value(fetched_at))
+            .filter(Column::Id.eq(id))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_pinned(ids: Vec<i32>, pinned: bool) -> crate::error::Result<()> {
+        Self::update_many()
+            .col_expr(Column::Pinned, Expr::value(pinned))
+            .filter(Column::Id.is_in(ids))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    /// 只保留 `ids` 中没有固定的
+    pub async fn filter_unpinned(ids: Vec<i32>) -> crate::error::Result<Vec<i32>> {
+        Ok(Self::find()
+            .filter(Column::Id.is_in(ids))
+            .filter(Column::Pinned.eq(false))
+            .all(database())
+            .await?
+            .into_iter()
+            .map(|model| model.id)
+            .collect())
+    }
+
+    /// 返回删除的 id
+    pub async fn delete_unpinned() -> crate::error::Result<Vec<i32>> {
+        let ids = Self::find()
+            .filter(Column::Pinned.eq(false))
+            .all(database())
+            .await?
+            .into_iter()
+            .map(|model| model.id)
+            .collect::<Vec<_>>();
+        if !ids.is_empty() {
+            Self::delete_by_ids(ids.clone()).await?;
+        }
+        Ok(ids)
+    }
+
+    /// 删除 `ids` 中自动获取的, 手动选择和固定的保留, 返回删除的 id
+    pub async fn delete_auto(ids: Vec<i32>) -> crate::error::Result<Vec<i32>> {
+        let ids = Self::find()
+            .filter(Column::Id.is_in(ids))
+            .filter(Column::Manual.eq(false))
+            .filter(Column::Pinned.eq(false))
+            .all(database())
+            .await?
+            .into_iter()
+            .map(|model| model.id)
+            .collect::<Vec<_>>();
+        if !ids.is_empty() {
+            Self::delete_by_ids(ids.clone()).await?;
+        }
+        Ok(ids)
+    }
+
+    pub async fn delete_by_ids(ids: Vec<i32>) -> crate::error::Result<()> {
+        Self::delete_many()
+            .filter(Column::Id.is_in(ids))
+            .exec(database())
+            .await?;
+        Ok(())
+    }
+
+    pub async fn delete_all() -> crate::error::Result<()> {
+        Self::delete_many().exec(database()).await?;
+        Ok(())
+    }
+}
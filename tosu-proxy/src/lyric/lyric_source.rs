@@ -102,6 +102,19 @@ impl Lyric {
         Ok(result)
     }
 
+    /// 由带时间的行构造, 按时间排序
+    pub fn from_lines(lines: Vec<LyricLine>) -> Result<Self> {
+        if lines.is_empty() {
+            return Err(Error::LyricParse("lyric is empty"));
+        }
+        let mut lyric = Self {
+            lyrics: lines,
+            ..Self::default()
+        };
+        lyric.sort_lines();
+        Ok(lyric)
+    }
+
     pub fn to_json_cache(&self) -> Result<Vec<u8>> {
         if self.lyrics.is_empty() {
             return Err(Error::LyricParse("can not serialize empty lyric"));
@@ -232,7 +245,7 @@ impl Lyric {
                 .iter()
                 .enumerate()
                 .find(|(_, v)| v.time >= time)
-                .map(|(i, v)| (i, v.time))
+                .map(|(i, v)| (cursor + i, v.time))
                 .unwrap_or_else(|| {
                     let last = self.lyrics.last().unwrap();
                     (self.lyrics.len() - 1, last.time)
@@ -241,7 +254,6 @@ impl Lyric {
             if eq_f32(time, line_time) {
                 self.lyrics.get_mut(index).ok_or(Error::Impossible)
             } else {
-                self.end_time = time;
                 self.lyrics.insert(
                     index,
                     LyricLine {
@@ -295,9 +307,279 @@ impl Lyric {
     pub fn get_cursor(&self) -> usize {
         self.cursor
     }
+
+    /// 第一行的时间, 秒
+    pub fn start_time(&self) -> f32 {
+        self.lyrics.first().map_or(0f32, |line| line.time)
+    }
+
+    /// 最后一行的时间, 秒
+    pub fn end_time(&self) -> f32 {
+        self.lyrics.last().map_or(0f32, |line| line.time)
+    }
+
+    pub fn has_translation(&self) -> bool {
+        self.lyrics.iter().any(|line| line.translation.is_some())
+    }
+
+    /// 歌词的完整程度, 用于比较同一首歌的两份歌词
+    ///
+    /// 先比较有翻译的行数, 再比较有原文的行数
+    pub fn score(&self) -> (usize, usize) {
+        let count = |f: fn(&LyricLine) -> bool| self.lyrics.iter().filter(|line| f(line)).count();
+        (
+            count(|line| line.translation.is_some()),
+            count(|line| line.origin.is_some()),
+        )
+    }
+
+    /// 修改第 `index` 行, 为 `None` 的字段不修改, 空字符串表示清除; `time` 为秒
+    pub fn update_line(
+        &mut self,
+        index: usize,
+        time: Option<f32>,
+        origin: Option<String>,
+        translation: Option<String>,
+    ) -> Result<()> {
+        if let Some(time) = time {
+            check_time(time)?;
+        }
+        let line = self
+            .lyrics
+            .get_mut(index)
+            .ok_or_else(|| format!("没有第 {index} 行"))?;
+        if let Some(time) = time {
+            line.time = time;
+        }
+        if let Some(origin) = origin {
+            line.origin = non_empty(origin);
+        }
+        if let Some(translation) = translation {
+            line.translation = non_empty(translation);
+        }
+        self.sort_lines();
+        Ok(())
+    }
+
+    /// 按时间插入一行, 时间相同时插在已有行之后, 返回插入的位置
+    pub fn insert_line(
+        &mut self,
+        time: f32,
+        origin: Option<String>,
+        translation: Option<String>,
+    ) -> Result<usize> {
+        check_time(time)?;
+        let index = self.lyrics.partition_point(|line| line.time <= time);
+        self.lyrics.insert(
+            index,
+            LyricLine {
+                time,
+                origin: origin.and_then(non_empty),
+                translation: translation.and_then(non_empty),
+            },
+        );
+        self.sort_lines();
+        Ok(index)
+    }
+
+    /// 删除从 `start` 开始的 `count` 行, 至少保留一行
+    pub fn remove_lines(&mut self, start: usize, count: usize) -> Result<()> {
+        let end = self.line_range(start, count)?;
+        if end - start >= self.lyrics.len() {
+            return Err("不能删除全部歌词".into());
+        }
+        self.lyrics.drain(start..end);
+        self.sort_lines();
+        Ok(())
+    }
+
+    /// 从 `start` 开始的 `count` 行整体移动 `seconds` 秒, 移到 0 之前的按 0 处理
+    pub fn shift_lines(&mut self, start: usize, count: usize, seconds: f32) -> Result<()> {
+        if !seconds.is_finite() {
+            return Err("时间无效".into());
+        }
+        let end = self.line_range(start, count)?;
+        for line in &mut self.lyrics[start..end] {
+            line.time = (line.time + seconds).max(0f32);
+        }
+        self.sort_lines();
+        Ok(())
+    }
+
+    /// 返回范围的结尾, 超出部分截断
+    fn line_range(&self, start: usize, count: usize) -> Result<usize> {
+        if start >= self.lyrics.len() || count == 0 {
+            return Err(format!("没有第 {start} 行").into());
+        }
+        Ok(self.lyrics.len().min(start.saturating_add(count)))
+    }
+
+    /// 编辑后重新按时间排序, 时间相同的保持原来的顺序
+    fn sort_lines(&mut self) {
+        self.lyrics.sort_by(|a, b| a.time.total_cmp(&b.time));
+        self.end_time = self.lyrics.last().map_or(-1.0, |line| line.time);
+        self.cursor = 0;
+    }
+
+    /// 把 `other` 的翻译按原文对齐到当前时间轴上, 返回补上翻译的行数
+    ///
+    /// 两边的时间轴可能有偏移, 所以按原文文本顺序匹配而不是按时间;
+    /// 能匹配上的原文行少于 [`MERGE_MIN_RATIO`] 时认为不是同一首歌, 不做修改
+    pub fn merge_translation(&mut self, other: &Lyric) -> usize {
+        let other_lines = other
+            .lyrics
+            .iter()
+            .filter_map(|line| Some((normalize_line(line.origin.as_deref()?), line)))
+            .filter(|(text, _)| !text.is_empty())
+            .collect::<Vec<_>>();
+
+        let mut total = 0;
+        let mut matched = vec![];
+        let mut start = 0;
+        for (index, line) in self.lyrics.iter().enumerate() {
+            let Some(origin) = line.origin.as_deref() else {
+                continue;
+            };
+            let text = normalize_line(origin);
+            if text.is_empty() {
+                continue;
+            }
+            total += 1;
+            // 只向后找, 保证顺序一致, 重复的副歌也能对上
+            let end = other_lines.len().min(start + MERGE_LOOKAHEAD);
+            let found = other_lines[start..end]
+                .iter()
+                .position(|(other_text, _)| *other_text == text);
+            if let Some(offset) = found {
+                start += offset + 1;
+                matched.push((index, other_lines[start - 1].1.translation.clone()));
+            }
+        }
+
+        if total == 0 || (matched.len() as f32) < total as f32 * MERGE_MIN_RATIO {
+            return 0;
+        }
+
+        let mut count = 0;
+        for (index, translation) in matched {
+            let line = &mut self.lyrics[index];
+            if line.translation.is_none() && translation.is_some() {
+                line.translation = translation;
+                count += 1;
+            }
+        }
+        count
+    }
+}
+
+/// 合并翻译时, 原文能匹配上的最低比例
+pub const MERGE_MIN_RATIO: f32 = 0.6;
+/// 合并翻译时, 每行最多向后查找的行数
+const MERGE_LOOKAHEAD: usize = 8;
+
+/// 忽略大小写, 空白与标点
+fn normalize_line(line: &str) -> String {
+    line.chars()
+        .filter(|c| c.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+fn check_time(time: f32) -> Result<()> {
+    if !time.is_finite() || time < 0f32 {
+        return Err(format!("时间无效: {time}").into());
+    }
+    Ok(())
+}
+
+fn non_empty(text: String) -> Option<String> {
+    let text = text.trim();
+    (!text.is_empty()).then(|| text.to_string())
 }
 
 #[inline]
 fn eq_f32(a: f32, b: f32) -> bool {
     (a - b).abs() < 1e-4
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ORIGIN: &str = "[00:00.00]Clair de lune - Claude Debussy\n[00:12.50]月光落在安静的湖面\n[00:25.30]风吹过窗前的琴键\n[00:38.10]夜色慢慢变得温柔\n[00:51.75]梦里还有那首旋律\n";
+
+    #[test]
+    fn test_merge_translation() -> Result<()> {
+        let mut lyric = Lyric::parse(ORIGIN, None, None)?;
+        // 另一个源的时间轴整体晚了 1 秒, 标点也不一样
+        let other = Lyric::parse(
+            "[00:01.00]作词 : Fixture Writer\n[00:13.50]月光落在安静的湖面。\n[00:26.30]风吹过窗前的琴键\n[00:39.10]夜色慢慢变得温柔\n[00:52.75]梦里还有那首旋律\n",
+            Some(
+                "[00:13.50]Moonlight falls on the quiet lake\n[00:26.30]The wind brushes the keys by the window\n[00:52.75]The melody still lingers in my dream\n",
+            ),
+            None,
+        )?;
+        assert_eq!(lyric.merge_translation(&other), 3);
+        let lines = lyric.get_lyrics();
+        assert_eq!(lines.len(), 5);
+        assert_eq!(lines[0].translation, None);
+        assert_eq!(
+            lines[1].translation.as_deref(),
+            Some("Moonlight falls on the quiet lake")
+        );
+        assert_eq!(lines[3].translation, None);
+        assert_eq!(
+            lines[4].translation.as_deref(),
+            Some("The melody still lingers in my dream")
+        );
+        Ok(())
+    }
+
+    #[test]
+    fn test_edit_lines() -> Result<()> {
+        let mut lyric = Lyric::parse(ORIGIN, None, None)?;
+        lyric.update_line(1, Some(30.0), None, Some("Moonlight".to_string()))?;
+        let lines = lyric.get_lyrics();
+        // 改了时间后重新排序
+        assert_eq!(lines[2].origin.as_deref(), Some("月光落在安静的湖面"));
+        assert_eq!(lines[2].translation.as_deref(), Some("Moonlight"));
+        lyric.update_line(2, None, None, Some(" ".to_string()))?;
+        assert_eq!(lyric.get_lyrics()[2].translation, None);
+        assert!(lyric.update_line(9, None, None, None).is_err());
+        assert!(lyric.update_line(0, Some(-1.0), None, None).is_err());
+
+        assert_eq!(lyric.insert_line(60.0, Some("尾声".to_string()), None)?, 5);
+        assert_eq!(lyric.end_time(), 60.0);
+        assert_eq!(lyric.insert_line(12.5, Some("插入".to_string()), None)?, 1);
+
+        // 后半段整体提前 1 秒
+        lyric.shift_lines(4, usize::MAX, -1.0)?;
+        let times = lyric
+            .get_lyrics()
+            .iter()
+            .map(|line| line.time)
+            .collect::<Vec<_>>();
+        assert_eq!(times, [0.0, 12.5, 25.3, 30.0, 37.1, 50.75, 59.0]);
+        lyric.shift_lines(0, 1, -5.0)?;
+        assert_eq!(lyric.start_time(), 0.0);
+
+        lyric.remove_lines(1, 2)?;
+        assert_eq!(lyric.get_lyrics().len(), 5);
+        assert!(lyric.remove_lines(0, 5).is_err());
+        assert!(lyric.remove_lines(5, 1).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_merge_translation_other_song() -> Result<()> {
+        let mut lyric = Lyric::parse(ORIGIN, None, None)?;
+        let other = Lyric::parse(
+            "[00:12.50]月光落在安静的湖面\n[00:25.30]完全不同的一句\n[00:38.10]另一首歌\n",
+            Some("[00:12.50]Moonlight falls on the quiet lake\n"),
+            None,
+        )?;
+        assert_eq!(lyric.merge_translation(&other), 0);
+        assert!(!lyric.has_translation());
+        Ok(())
+    }
+}
@@ -1,27 +1,49 @@
 mod lyric_source;
 mod source;
+mod tap_recorder;
+mod validate;
 
 use crate::error::{Error, Result};
 pub use lyric_source::*;
 use sea_orm::EntityTrait;
+#[cfg(test)]
+pub(crate) use source::fixture;
 pub use source::*;
+pub use tap_recorder::TapRecorder;
+pub use validate::validate_lyric;
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::error::*;
+    use crate::lyric::source::fixture::FixtureServer;
+
     #[tokio::test]
     async fn test_parse_lyric() -> Result<()> {
-        let title = "稻香";
-        let all_music = QQLyricSource.search_all_music(title, "周杰伦").await?;
-        let mut lyric = QQ_LYRIC_SOURCE
-            .search_lyrics(&all_music, title, 203000, "周杰伦")
+        let title = "Clair de lune";
+        let server = FixtureServer::start_all().await;
+        let source = QQLyricSource::with_base_url(server.url());
+        let all_music = source.search_all_music(title, "Debussy").await?;
+        let lyric = source
+            .search_lyrics(&all_music, title, 300000, "Debussy")
             .await?
-            .ok_or::<Error>("没找到对应歌曲".into())?;
+            .ok_or::<Error>("没找到对应歌曲".into())?
+            .1;
         let l1 = &lyric.lyric.unwrap();
         let l2 = lyric.trans.as_deref();
         let l = Lyric::parse(l1, l2, Some(title))?;
-        println!("{l:?}");
+        let lines = l.get_lyrics();
+        // 标题与第一行同为 0 秒, 会被第一行覆盖
+        assert_eq!(lines.len(), 6);
+        assert_eq!(
+            lines[0].origin.as_deref(),
+            Some("Clair de lune - Claude Debussy")
+        );
+        assert_eq!(lines[5].origin.as_deref(), Some("等待黎明悄悄来临"));
+        assert_eq!(
+            lines[5].translation.as_deref(),
+            Some("Waiting for the dawn to come")
+        );
         Ok(())
     }
 }
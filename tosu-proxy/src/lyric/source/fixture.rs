@@ -0,0 +1,215 @@
+//! 测试用的本地 HTTP 服务, 按路径返回录制好的接口数据, 让歌词源的测试不依赖外网
+//! 也可以作为 http 代理使用
+
+use super::{LyricSourceEnum, NeteaseLyricSource, QQLyricSource};
+use crate::osu_source::OsuSongInfo;
+use std::sync::atomic::{AtomicI64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::Notify;
+use tokio::task::JoinHandle;
+
+pub(crate) const QQ_SEARCH_PATH: &str = "/soso/fcgi-bin/client_search_cp";
+pub(crate) const QQ_LYRIC_PATH: &str = "/lyric/fcgi-bin/fcg_query_lyric_new.fcg";
+pub(crate) const NETEASE_SEARCH_PATH: &str = "/search/get";
+pub(crate) const NETEASE_LYRIC_PATH: &str = "/song/lyric";
+
+pub(crate) const QQ_SEARCH: &str = include_str!("fixtures/qq_search.json");
+pub(crate) const QQ_LYRIC: &str = include_str!("fixtures/qq_lyric.json");
+pub(crate) const NETEASE_SEARCH: &str = include_str!("fixtures/netease_search.json");
+pub(crate) const NETEASE_LYRIC: &str = include_str!("fixtures/netease_lyric.json");
+pub(crate) const NETEASE_PURE_MUSIC: &str = include_str!("fixtures/netease_pure_music.json");
+
+#[derive(Debug)]
+pub(crate) struct Fixture {
+    path: &'static str,
+    status: u16,
+    body: &'static str,
+    // 命中多少次后失效, 用于模拟先失败后成功
+    times: Option<usize>,
+    hits: AtomicUsize,
+    // 模拟慢速接口, 等测试放行后才响应
+    gate: Option<Arc<Notify>>,
+}
+
+impl Fixture {
+    pub(crate) fn json(path: &'static str, body: &'static str) -> Self {
+        Self {
+            path,
+            status: 200,
+            body,
+            times: None,
+            hits: AtomicUsize::new(0),
+            gate: None,
+        }
+    }
+
+    pub(crate) fn status(mut self, status: u16) -> Self {
+        self.status = status;
+        self
+    }
+
+    pub(crate) fn times(mut self, times: usize) -> Self {
+        self.times = Some(times);
+        self
+    }
+
+    /// `gate` 每次 `notify_one` 放行一个请求
+    pub(crate) fn gate(mut self, gate: Arc<Notify>) -> Self {
+        self.gate = Some(gate);
+        self
+    }
+
+    fn try_hit(&self, path: &str) -> bool {
+        if self.path != path {
+            return false;
+        }
+        let Some(times) = self.times else {
+            return true;
+        };
+        self.hits
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |hits| {
+                (hits < times).then_some(hits + 1)
+            })
+            .is_ok()
+    }
+}
+
+type RequestLog = Arc<Mutex<Vec<String>>>;
+
+/// 监听 `127.0.0.1` 的随机端口, drop 时关闭
+pub(crate) struct FixtureServer {
+    url: String,
+    requests: RequestLog,
+    handle: JoinHandle<()>,
+}
+
+impl FixtureServer {
+    pub(crate) async fn start(fixtures: Vec<Fixture>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("cannot bind fixture server");
+        let addr = listener.local_addr().expect("cannot get fixture address");
+        let fixtures = Arc::new(fixtures);
+        let requests = RequestLog::default();
+        let log = Arc::clone(&requests);
+        let handle = tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(handle_connection(
+                    stream,
+                    Arc::clone(&fixtures),
+                    Arc::clone(&log),
+                ));
+            }
+        });
+        Self {
+            url: format!("http://{addr}"),
+            requests,
+            handle,
+        }
+    }
+
+    /// QQ 与 网易云 各自的接口都挂在一个服务上
+    pub(crate) async fn start_all() -> Self {
+        Self::start(vec![
+            Fixture::json(QQ_SEARCH_PATH, QQ_SEARCH),
+            Fixture::json(QQ_LYRIC_PATH, QQ_LYRIC),
+            Fixture::json(NETEASE_SEARCH_PATH, NETEASE_SEARCH),
+            Fixture::json(NETEASE_LYRIC_PATH, NETEASE_LYRIC),
+        ])
+        .await
+    }
+
+    pub(crate) fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// 收到的全部请求头(原文)
+    pub(crate) fn requests(&self) -> Vec<String> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    /// 指向本服务的 QQ 歌词源, 歌词源需要 `'static`, 测试里直接泄漏
+    pub(crate) fn qq_source(&self) -> LyricSourceEnum {
+        let source = Box::leak(Box::new(QQLyricSource::with_base_url(self.url())));
+        LyricSourceEnum::QQLyricSource(source)
+    }
+
+    pub(crate) fn netease_source(&self) -> LyricSourceEnum {
+        let source = Box::leak(Box::new(NeteaseLyricSource::with_base_url(self.url())));
+        LyricSourceEnum::NeteaseLyricSource(source)
+    }
+}
+
+/// 能匹配上录制数据的歌曲, 其它字段用结构体更新语法覆盖
+pub(crate) fn test_song(bid: i64, sid: i64, title: &str) -> OsuSongInfo {
+    OsuSongInfo {
+        bid,
+        sid,
+        length: 300_000,
+        now: 0,
+        artist: "Claude Debussy".to_string(),
+        artist_unicode: "Claude Debussy".to_string(),
+        title: title.to_string(),
+        title_unicode: title.to_string(),
+        browsing: false,
+        audio_hash: None,
+        audio_path: None,
+    }
+}
+
+/// 每次返回不同的谱面, sid 为 bid - 1, 不用在各个测试里挑不冲突的 bid
+pub(crate) fn unique_song(title: &str) -> OsuSongInfo {
+    // 避开手写的 bid
+    static NEXT: AtomicI64 = AtomicI64::new(1_000_000);
+    let bid = NEXT.fetch_add(2, Ordering::SeqCst) + 1;
+    test_song(bid, bid - 1, title)
+}
+
+impl Drop for FixtureServer {
+    fn drop(&mut self) {
+        self.handle.abort();
+    }
+}
+
+async fn handle_connection(mut stream: TcpStream, fixtures: Arc<Vec<Fixture>>, log: RequestLog) {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    // 只处理 GET, 读到请求头结束即可
+    while !head.windows(4).any(|w| w == b"\r\n\r\n") {
+        match stream.read(&mut buffer).await {
+            Ok(0) | Err(_) => return,
+            Ok(n) => head.extend_from_slice(&buffer[..n]),
+        }
+    }
+    let head = String::from_utf8_lossy(&head).to_string();
+    log.lock().unwrap().push(head.clone());
+    let target = head.split_whitespace().nth(1).unwrap_or("/");
+    let path = request_path(target);
+
+    let (status, body, gate) = fixtures
+        .iter()
+        .find(|f| f.try_hit(path))
+        .map(|f| (f.status, f.body, f.gate.clone()))
+        .unwrap_or((404, "", None));
+    if let Some(gate) = gate {
+        gate.notified().await;
+    }
+    let response = format!(
+        "HTTP/1.1 {status} {}\r\nContent-Type: application/json; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        if status == 200 { "OK" } else { "Fixture" },
+        body.len(),
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
+
+fn request_path(target: &str) -> &str {
+    // 作为代理时收到的是完整地址 `http://host/path?query`
+    let target = match target.split_once("://") {
+        Some((_, rest)) => rest.find('/').map_or("/", |i| &rest[i..]),
+        None => target,
+    };
+    target.split('?').next().unwrap_or(target)
+}
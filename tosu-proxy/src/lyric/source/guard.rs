@@ -0,0 +1,374 @@
+//! 歌词源请求保护: 限速, 失败重试(带随机抖动的指数退避), 熔断
+
+use crate::error::{Error, Result};
+use rand::Rng;
+use reqwest::{RequestBuilder, Response, StatusCode};
+use serde::Serialize;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+tokio::task_local! {
+    static BACKGROUND: ();
+}
+
+/// 在 `f` 中发出的请求视为后台请求: 不重试, 熔断或试探恢复期间直接跳过, 结果不计入熔断
+///
+/// 用于补充歌词标记之类可有可无的请求, 避免它们把源熔断, 影响正常的搜索
+pub async fn background<F: Future>(f: F) -> F::Output {
+    BACKGROUND.scope((), f).await
+}
+
+fn is_background() -> bool {
+    BACKGROUND.try_with(|_| ()).is_ok()
+}
+
+#[derive(Debug, Clone)]
+pub struct RequestPolicy {
+    /// 两次请求之间的最小间隔
+    pub min_interval: Duration,
+    /// 临时性错误的最大重试次数
+    pub max_retries: u32,
+    /// 第 n 次重试前等待 `backoff_base * 2^n`, 不超过 `backoff_max`
+    pub backoff_base: Duration,
+    pub backoff_max: Duration,
+    /// 连续失败多少次后熔断
+    pub failure_threshold: u32,
+    /// 熔断后暂停请求的时长
+    pub open_duration: Duration,
+}
+
+impl Default for RequestPolicy {
+    fn default() -> Self {
+        Self {
+            min_interval: Duration::from_millis(300),
+            max_retries: 2,
+            backoff_base: Duration::from_millis(200),
+            backoff_max: Duration::from_secs(2),
+            failure_threshold: 5,
+            open_duration: Duration::from_secs(60),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CircuitState {
+    /// 正常
+    Closed,
+    /// 熔断中, 直接跳过
+    Open,
+    /// 熔断到期, 放行请求试探是否恢复
+    HalfOpen,
+}
+
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceStatus {
+    pub name: String,
+    pub state: CircuitState,
+    pub consecutive_failures: u32,
+    pub total_requests: u64,
+    pub total_failures: u64,
+    /// 熔断剩余时间, 毫秒
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_after: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_error: Option<String>,
+}
+
+#[derive(Default)]
+struct GuardState {
+    consecutive_failures: u32,
+    open_until: Option<Instant>,
+    half_open: bool,
+    total_requests: u64,
+    total_failures: u64,
+    last_error: Option<String>,
+}
+
+pub struct SourceGuard {
+    name: &'static str,
+    policy: RequestPolicy,
+    state: Mutex<GuardState>,
+    // 上次请求的时间, 等待间隔时持有锁, 以保证同一个源的请求串行
+    last_request: tokio::sync::Mutex<Option<Instant>>,
+}
+
+impl SourceGuard {
+    pub fn new(name: &'static str, policy: RequestPolicy) -> Self {
+        Self {
+            name,
+            policy,
+            state: Mutex::new(GuardState::default()),
+            last_request: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// `request` 每次重试都会重新调用以构建请求
+    ///
+    /// 网络错误 / 超时 / 429 / 5xx 视为临时错误, 会重试, 重试耗尽后计入熔断;
+    /// 其余状态码原样返回, 交给调用方处理
+    pub async fn send<F>(&self, request: F) -> Result<Response>
+    where
+        F: Fn() -> RequestBuilder + Send,
+    {
+        let background = is_background();
+        if background {
+            self.before_background_request()?;
+        } else {
+            self.before_request()?;
+        }
+        let mut attempt = 0;
+        loop {
+            self.wait_interval().await;
+            let err = match request().send().await {
+                Ok(response) if !is_transient_status(response.status()) => {
+                    if !background {
+                        self.on_success();
+                    }
+                    return Ok(response);
+                }
+                Ok(response) => {
+                    Error::Runtime(format!("{} 请求失败: {}", self.name, response.status()))
+                }
+                Err(err) if is_transient_error(&err) => err.into(),
+                Err(err) => {
+                    // 构建请求之类的错误, 重试也没有意义, 也不是源的问题
+                    return Err(err.into());
+                }
+            };
+
+            if background {
+                debug!("{} 后台请求失败: {}", self.name, err);
+                return Err(err);
+            }
+            if attempt >= self.policy.max_retries {
+                self.on_failure(&err);
+                return Err(err);
+            }
+            let delay = self.backoff(attempt);
+            attempt += 1;
+            debug!(
+                "{} 请求失败, {}ms 后第 {} 次重试: {}",
+                self.name,
+                delay.as_millis(),
+                attempt,
+                err
+            );
+            sleep(delay).await;
+        }
+    }
+
+    pub fn status(&self) -> SourceStatus {
+        let state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let (circuit, retry_after) = match state.open_until {
+            Some(until) if until > now => {
+                (CircuitState::Open, Some((until - now).as_millis() as u64))
+            }
+            Some(_) => (CircuitState::HalfOpen, None),
+            None if state.half_open => (CircuitState::HalfOpen, None),
+            None => (CircuitState::Closed, None),
+        };
+        SourceStatus {
+            name: self.name.to_string(),
+            state: circuit,
+            consecutive_failures: state.consecutive_failures,
+            total_requests: state.total_requests,
+            total_failures: state.total_failures,
+            retry_after,
+            last_error: state.last_error.clone(),
+        }
+    }
+
+    fn before_request(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(until) = state.open_until {
+            let now = Instant::now();
+            if until > now {
+                return Err(Error::Runtime(format!(
+                    "{} 已熔断, {}s 后恢复",
+                    self.name,
+                    (until - now).as_secs() + 1
+                )));
+            }
+            state.open_until = None;
+            state.half_open = true;
+            debug!("{} 熔断到期, 尝试恢复", self.name);
+        }
+        state.total_requests += 1;
+        Ok(())
+    }
+
+    /// 后台请求不参与熔断的状态切换, 只在正常状态下放行
+    fn before_background_request(&self) -> Result<()> {
+        let mut state = self.state.lock().unwrap();
+        if state.open_until.is_some() || state.half_open {
+            return Err(Error::Runtime(format!(
+                "{} 熔断中, 跳过后台请求",
+                self.name
+            )));
+        }
+        state.total_requests += 1;
+        Ok(())
+    }
+
+    fn on_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        if state.half_open {
+            info!("{} 已恢复", self.name);
+        }
+        state.half_open = false;
+        state.consecutive_failures = 0;
+    }
+
+    fn on_failure(&self, err: &Error) {
+        let mut state = self.state.lock().unwrap();
+        state.total_failures += 1;
+        state.consecutive_failures += 1;
+        state.last_error = Some(err.to_string());
+        if state.half_open || state.consecutive_failures >= self.policy.failure_threshold {
+            state.half_open = false;
+            state.open_until = Some(Instant::now() + self.policy.open_duration);
+            warn!(
+                "{} 连续失败 {} 次, 熔断 {}s: {}",
+                self.name,
+                state.consecutive_failures,
+                self.policy.open_duration.as_secs(),
+                err
+            );
+        } else {
+            warn!("{} 请求失败: {}", self.name, err);
+        }
+    }
+
+    async fn wait_interval(&self) {
+        let mut last = self.last_request.lock().await;
+        if let Some(last) = *last {
+            let elapsed = last.elapsed();
+            if elapsed < self.policy.min_interval {
+                sleep(self.policy.min_interval - elapsed).await;
+            }
+        }
+        *last = Some(Instant::now());
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let delay = self
+            .policy
+            .backoff_base
+            .saturating_mul(2u32.saturating_pow(attempt))
+            .min(self.policy.backoff_max);
+        let half = delay.as_millis() as u64 / 2;
+        Duration::from_millis(half + rand::rng().random_range(0..=half))
+    }
+}
+
+fn is_transient_status(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+fn is_transient_error(err: &reqwest::Error) -> bool {
+    err.is_timeout() || err.is_connect() || err.is_request()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::fixture::*;
+    use super::*;
+    use reqwest::Client;
+
+    fn test_policy() -> RequestPolicy {
+        RequestPolicy {
+            min_interval: Duration::ZERO,
+            max_retries: 2,
+            backoff_base: Duration::from_millis(1),
+            backoff_max: Duration::from_millis(5),
+            failure_threshold: 2,
+            open_duration: Duration::from_secs(60),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_retry_transient_error() -> Result<()> {
+        let server = FixtureServer::start(vec![
+            Fixture::json(QQ_SEARCH_PATH, "").status(503).times(2),
+            Fixture::json(QQ_SEARCH_PATH, QQ_SEARCH),
+        ])
+        .await;
+        let client = Client::new();
+        let url = format!("{}{}", server.url(), QQ_SEARCH_PATH);
+        let guard = SourceGuard::new("test", test_policy());
+
+        let response = guard.send(|| client.get(&url)).await?;
+        assert!(response.status().is_success());
+        assert_eq!(server.requests().len(), 3);
+        let status = guard.status();
+        assert_eq!(status.state, CircuitState::Closed);
+        assert_eq!(status.consecutive_failures, 0);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_circuit_open() -> Result<()> {
+        let server =
+            FixtureServer::start(vec![Fixture::json(QQ_SEARCH_PATH, "").status(500)]).await;
+        let client = Client::new();
+        let url = format!("{}{}", server.url(), QQ_SEARCH_PATH);
+        let guard = SourceGuard::new("test", test_policy());
+
+        assert!(guard.send(|| client.get(&url)).await.is_err());
+        assert!(guard.send(|| client.get(&url)).await.is_err());
+        // 两次调用, 每次 1 + 2 次重试
+        assert_eq!(server.requests().len(), 6);
+        assert_eq!(guard.status().state, CircuitState::Open);
+
+        // 熔断期间不会再发出请求
+        assert!(guard.send(|| client.get(&url)).await.is_err());
+        assert_eq!(server.requests().len(), 6);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_error_not_retried() -> Result<()> {
+        let server =
+            FixtureServer::start(vec![Fixture::json(QQ_SEARCH_PATH, "").status(404)]).await;
+        let client = Client::new();
+        let url = format!("{}{}", server.url(), QQ_SEARCH_PATH);
+        let guard = SourceGuard::new("test", test_policy());
+
+        let response = guard.send(|| client.get(&url)).await?;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        assert_eq!(server.requests().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_background_not_counted() -> Result<()> {
+        let server =
+            FixtureServer::start(vec![Fixture::json(QQ_SEARCH_PATH, "").status(500)]).await;
+        let client = Client::new();
+        let url = format!("{}{}", server.url(), QQ_SEARCH_PATH);
+        let guard = SourceGuard::new("test", test_policy());
+
+        for _ in 0..3 {
+            assert!(background(guard.send(|| client.get(&url))).await.is_err());
+        }
+        // 不重试, 也不会熔断
+        assert_eq!(server.requests().len(), 3);
+        let status = guard.status();
+        assert_eq!(status.state, CircuitState::Closed);
+        assert_eq!(status.consecutive_failures, 0);
+
+        // 熔断后后台请求直接跳过
+        assert!(guard.send(|| client.get(&url)).await.is_err());
+        assert!(guard.send(|| client.get(&url)).await.is_err());
+        assert_eq!(guard.status().state, CircuitState::Open);
+        let sent = server.requests().len();
+        assert!(background(guard.send(|| client.get(&url))).await.is_err());
+        assert_eq!(server.requests().len(), sent);
+        Ok(())
+    }
+}
@@ -1,18 +1,32 @@
+#[cfg(test)]
+pub(crate) mod fixture;
+mod guard;
 mod netease;
 mod qq;
 
-use super::Lyric;
+use super::{Lyric, validate_lyric};
+use crate::config::{GLOBAL_CONFIG, SourceConfig};
 use crate::error::{Error, Result};
 use async_trait::async_trait;
+pub use guard::{CircuitState, RequestPolicy, SourceGuard, SourceStatus, background};
 pub use netease::NeteaseLyricSource;
 pub use qq::QQLyricSource;
-use reqwest::Client;
+use reqwest::header::{COOKIE, HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Client, Proxy};
+use sea_orm::sqlx::types::chrono::{DateTime, FixedOffset};
 use serde::{Deserialize, Serialize};
 use std::ops::Deref;
 use std::sync::LazyLock;
+use tracing::{debug, warn};
 
 const NO_LENGTH: u32 = 0;
 const ALLOW_OFFSET: u32 = 15000;
+/// 自动搜索时每个源取的结果数
+const SEARCH_LIMIT: u32 = 5;
+/// 手动搜索时单页结果数的上限
+pub const MAX_SEARCH_LIMIT: u32 = 30;
+/// 手动搜索时页码的上限, 歌词源也不会返回更靠后的结果
+pub const MAX_SEARCH_PAGE: u32 = 100;
 
 macro_rules! static_source {
     ($($name:ident : $t:ident),* $(,)?) => {
@@ -20,21 +34,28 @@ macro_rules! static_source {
             pub static $name: LazyLock<$t> = LazyLock::new(<$t>::default);
         )*
 
+        #[derive(Clone, Copy)]
         pub enum LyricSourceEnum {
             $($t(&'static $t)),*
         }
 
         #[async_trait]
         impl LyricSource for LyricSourceEnum {
-            fn name(&self) -> &str {
+            fn name(&self) -> &'static str {
                 match self {
                     $(Self::$t(source) => source.name()),*
                 }
             }
 
-            async fn search_music(&self, title: &str) -> Result<Vec<SongInfo>> {
+            fn guard(&self) -> &SourceGuard {
+                match self {
+                    $(Self::$t(source) => source.guard()),*
+                }
+            }
+
+            async fn search_page(&self, keyword: &str, page: u32, limit: u32) -> Result<Vec<SongInfo>> {
                 match self {
-                    $(Self::$t(source) => source.search_music(title).await),*
+                    $(Self::$t(source) => source.search_page(keyword, page, limit).await),*
                 }
             }
 
@@ -46,6 +67,15 @@ macro_rules! static_source {
         }
 
         impl LyricSourceEnum {
+            pub fn all() -> Vec<Self> {
+                vec![$(Self::$t(&*$name)),*]
+            }
+
+            /// 在 `sources` 中按名称查找
+            pub fn find(sources: &[Self], name: &str) -> Option<Self> {
+                sources.iter().find(|source| source.name() == name).copied()
+            }
+
             pub fn get_by_name(name: &str) -> Option<Self> {
                 let result = match name {
                     $(name if name == $name.name() => Self::$t(&*$name),)*
@@ -63,12 +93,57 @@ static_source! {
 }
 
 static CLIENT: LazyLock<Client> = LazyLock::new(|| {
-    Client::builder()
-        .timeout(std::time::Duration::from_secs(5))
-        .build()
-        .unwrap()
+    build_client(&GLOBAL_CONFIG.source).expect("无法创建歌词源的 http client, 请检查 `source` 配置")
 });
 
+/// 按配置的代理 / user-agent 创建 client
+pub fn build_client(config: &SourceConfig) -> Result<Client> {
+    let mut builder = Client::builder().timeout(std::time::Duration::from_secs(5));
+    if let Some(proxy) = &config.proxy {
+        builder = builder.proxy(Proxy::all(proxy)?);
+    }
+    if let Some(user_agent) = &config.user_agent {
+        builder = builder.user_agent(user_agent);
+    }
+    Ok(builder.build()?)
+}
+
+/// 配置中属于 `source_name` 的请求头与 cookie, 无效的项会被忽略
+pub fn source_headers(config: &SourceConfig, source_name: &str) -> HeaderMap {
+    let mut result = HeaderMap::new();
+    let headers = config
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case(source_name))
+        .flat_map(|(_, headers)| headers.iter());
+    for (key, value) in headers {
+        match (
+            HeaderName::try_from(key.as_str()),
+            HeaderValue::try_from(value.as_str()),
+        ) {
+            (Ok(key), Ok(value)) => {
+                result.insert(key, value);
+            }
+            _ => warn!("忽略无效的请求头 {source_name}: {key}"),
+        }
+    }
+
+    let cookie = config
+        .cookies
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(source_name))
+        .map(|(_, cookie)| cookie);
+    if let Some(cookie) = cookie {
+        match HeaderValue::try_from(cookie.as_str()) {
+            Ok(value) => {
+                result.insert(COOKIE, value);
+            }
+            Err(_) => warn!("忽略无效的 cookie {source_name}"),
+        }
+    }
+    result
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SongInfoKey {
     #[serde(rename = "type")]
@@ -76,19 +151,100 @@ pub struct SongInfoKey {
     pub key: String,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl SongInfoKey {
+    pub fn source(&self) -> Result<LyricSourceEnum> {
+        LyricSourceEnum::get_by_name(&self.source_type)
+            .ok_or_else(|| format!("no source type is {}", self.source_type).into())
+    }
+
+    /// 获取并解析对应的歌词
+    pub async fn fetch_lyric(&self) -> Result<Lyric> {
+        let lyric = self.source()?.fetch_lyrics(&self.key).await?;
+        lyric.try_into()
+    }
+}
+
+/// 歌词来自哪首歌, 翻译可能是从另一个源合并来的
+#[derive(Debug, Clone)]
+pub struct LyricOrigin {
+    pub lyric: SongInfoKey,
+    pub trans: Option<SongInfoKey>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
 pub struct SongInfo {
     pub title: String,
     pub artist: String,
     /// 毫秒
     pub length: u32,
     pub key: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub album: Option<String>,
+    /// 封面图片地址
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cover: Option<String>,
+    /// 源内部的歌曲 id, 与 `key` 不一定相同 (QQ 的 `key` 是 songmid)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub source_id: Option<String>,
+    /// 发行年份
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub year: Option<i32>,
+    // 以下为 `None` 时表示还不知道, 获取过歌词后补上
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_translation: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_romanization: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub has_word_timing: Option<bool>,
+    /// 已经尝试过获取歌词标记, 失败了也不再重试
+    #[serde(skip)]
+    pub lyric_flags_tried: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// 秒级时间戳转年份, 接口没给(0)时返回 `None`
+///
+/// 两个源的发行时间都是按东八区的零点给的, 按 UTC 算会差到前一年
+fn year_of_timestamp(secs: i64) -> Option<i32> {
+    if secs <= 0 {
+        return None;
+    }
+    let offset = FixedOffset::east_opt(8 * 3600)?;
+    DateTime::from_timestamp(secs, 0)
+        .map(|time| time.with_timezone(&offset))
+        .and_then(|time| time.format("%Y").to_string().parse().ok())
+}
+
+impl SongInfo {
+    pub fn is_lyric_flags_known(&self) -> bool {
+        self.has_translation.is_some()
+    }
+
+    pub fn set_lyric_flags(&mut self, lyric: &LyricResult) {
+        self.lyric_flags_tried = true;
+        self.has_translation = Some(lyric.trans.is_some());
+        // 接口本身不提供的信息保持未知
+        self.has_romanization = lyric.extras_known.then_some(lyric.roma.is_some());
+        self.has_word_timing = lyric.extras_known.then_some(lyric.word_timing);
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LyricResult {
     pub lyric: Option<String>,
     pub trans: Option<String>,
+    /// 罗马音
+    #[serde(default)]
+    pub roma: Option<String>,
+    /// 源是否提供逐字时间
+    #[serde(default)]
+    pub word_timing: bool,
+    /// 源的接口是否会返回罗马音和逐字歌词, 为 `false` 时 `roma`/`word_timing` 只表示未知
+    #[serde(default)]
+    pub extras_known: bool,
+    /// 源标记为纯音乐, 此时没有歌词
+    #[serde(default)]
+    pub instrumental: bool,
 }
 
 impl LyricResult {
@@ -112,8 +268,14 @@ impl TryInto<Lyric> for LyricResult {
 
 #[async_trait]
 pub trait LyricSource: Send + Sync {
-    fn name(&self) -> &str;
-    async fn search_music(&self, title: &str) -> Result<Vec<SongInfo>>;
+    fn name(&self) -> &'static str;
+    /// 该源的限速 / 重试 / 熔断状态
+    fn guard(&self) -> &SourceGuard;
+    /// 按关键字分页搜索, `page` 从 1 开始
+    async fn search_page(&self, keyword: &str, page: u32, limit: u32) -> Result<Vec<SongInfo>>;
+    async fn search_music(&self, title: &str) -> Result<Vec<SongInfo>> {
+        self.search_page(title, 1, SEARCH_LIMIT).await
+    }
     async fn fetch_lyrics(&self, song_id: &str) -> Result<LyricResult>;
     fn preferred_song<'a>(
         songs: &'a [SongInfo],
@@ -141,13 +303,18 @@ pub trait LyricSource: Send + Sync {
     }
 
     /// length 设为 0, 不应用过滤
+    ///
+    /// 同时返回歌词对应的歌曲, 只找到纯音乐时返回的结果 `instrumental` 为 `true`
+    ///
+    /// 单个候选获取失败时换下一个; 有候选失败且没有可用的歌词时返回错误,
+    /// 不能当作没有歌词记录下来
     async fn search_lyrics(
         &self,
         song_all: &[SongInfo],
         title: &str,
         length: u32,
         artist: &str,
-    ) -> Result<Option<LyricResult>> {
+    ) -> Result<Option<(SongInfo, LyricResult)>> {
         if song_all.is_empty() {
             return Ok(None);
         }
@@ -156,14 +323,38 @@ pub trait LyricSource: Send + Sync {
             return Ok(None);
         }
 
+        let mut instrumental = None;
+        let mut error = None;
         for info in song {
-            let lyrics = self.fetch_lyrics(&info.key).await?;
-            if !lyrics.is_none() {
-                return Ok(Some(lyrics));
+            let lyrics = match self.fetch_lyrics(&info.key).await {
+                Ok(lyrics) => lyrics,
+                Err(err) => {
+                    warn!("{} 获取 {} 的歌词失败: {}", self.name(), info.key, err);
+                    error = Some(err);
+                    continue;
+                }
+            };
+            if lyrics.instrumental && instrumental.is_none() {
+                instrumental = Some((info.clone(), lyrics));
+                continue;
+            }
+            if lyrics.is_none() {
+                continue;
+            }
+            let checked = TryInto::<Lyric>::try_into(lyrics.clone())
+                .and_then(|lyric| validate_lyric(&lyric, length));
+            match checked {
+                Ok(_) => return Ok(Some((info.clone(), lyrics))),
+                Err(err) => debug!("{} 跳过 {}: {}", self.name(), info.key, err),
             }
         }
 
-        Ok(None)
+        match (instrumental, error) {
+            // 所有候选都没有可用的歌词时, 才认为是纯音乐
+            (Some(instrumental), _) => Ok(Some(instrumental)),
+            (None, Some(err)) => Err(err),
+            (None, None) => Ok(None),
+        }
     }
 
     fn song_filter_length(song: &SongInfo, length: u32) -> bool {
@@ -187,30 +378,229 @@ pub trait LyricSource: Send + Sync {
 
 #[cfg(test)]
 mod tests {
+    use super::fixture::*;
     use super::*;
+    use std::collections::HashMap;
 
     const TITLE: &str = "Clair de lune";
     const ARTIST: &str = "Debussy";
 
     #[tokio::test]
     async fn test_qq_lyric_source() -> Result<()> {
-        let song_info = QQ_LYRIC_SOURCE.search_all_music(TITLE, ARTIST).await?;
+        let server = FixtureServer::start_all().await;
+        let source = QQLyricSource::with_base_url(server.url());
+        let song_info = source.search_all_music(TITLE, ARTIST).await?;
+        assert_eq!(song_info.len(), 2);
         let song = song_info.first().ok_or("not found song")?;
-        let song_name = &song.title;
-        let key = &song.key;
-        let lyric = QQ_LYRIC_SOURCE.fetch_lyrics(key).await?;
-        println!("{song:?}\n{lyric:?}");
+        assert_eq!(song.title, TITLE);
+        assert_eq!(song.artist, "Claude Debussy, Fixture Pianist");
+        assert_eq!(song.length, 301_000);
+        assert_eq!(song.key, "001Qu4I30eVFYb");
+        assert_eq!(song.album.as_deref(), Some("Debussy: Suite bergamasque"));
+        assert_eq!(
+            song.cover.as_deref(),
+            Some("https://y.gtimg.cn/music/photo_new/T002R300x300M000002MAeob3zLXwZ.jpg")
+        );
+        assert_eq!(song.source_id.as_deref(), Some("102065756"));
+        assert_eq!(song.year, Some(2010));
+        assert!(!song.is_lyric_flags_known());
+
+        let lyric = source.fetch_lyrics(&song.key).await?;
+        let origin = lyric.lyric.as_deref().ok_or("no lyric")?;
+        assert!(origin.contains("[00:12.50]月光落在安静的湖面"));
+        assert!(lyric.trans.is_some());
+        let mut song = song.clone();
+        song.set_lyric_flags(&lyric);
+        assert_eq!(song.has_translation, Some(true));
+        // QQ 的歌词接口不提供罗马音和逐字歌词
+        assert_eq!(song.has_romanization, None);
+        assert_eq!(song.has_word_timing, None);
         Ok(())
     }
 
     #[tokio::test]
     async fn test_netease_lyric_source() -> Result<()> {
-        let song_info = NETEASE_LYRIC_SOURCE.search_all_music(TITLE, ARTIST).await?;
+        let server = FixtureServer::start_all().await;
+        let source = NeteaseLyricSource::with_base_url(server.url());
+        let song_info = source.search_all_music(TITLE, ARTIST).await?;
         let song = song_info.first().ok_or("not found song")?;
-        let song_name = &song.title;
-        let key = &song.key;
-        let lyric = NETEASE_LYRIC_SOURCE.fetch_lyrics(key).await?;
-        println!("{song:?}\n{lyric:?}");
+        assert_eq!(song.title, TITLE);
+        assert_eq!(song.artist, "Claude Debussy");
+        assert_eq!(song.length, 300_826);
+        assert_eq!(song.key, "1827600118");
+        assert_eq!(song.album.as_deref(), Some("Suite bergamasque"));
+        assert_eq!(song.source_id.as_deref(), Some("1827600118"));
+        assert_eq!(song.year, Some(2021));
+
+        let lyric = source.fetch_lyrics(&song.key).await?;
+        assert!(lyric.roma.is_none());
+        assert!(!lyric.word_timing);
+        let mut flagged = song.clone();
+        flagged.set_lyric_flags(&lyric);
+        assert_eq!(flagged.has_romanization, Some(false));
+        assert_eq!(flagged.has_word_timing, Some(false));
+        let lyric: Lyric = lyric.try_into()?;
+        let lines = lyric.get_lyrics();
+        assert_eq!(lines.len(), 7);
+        let line = &lines[2];
+        assert_eq!(line.origin.as_deref(), Some("月光落在安静的湖面"));
+        assert_eq!(
+            line.translation.as_deref(),
+            Some("Moonlight falls on the quiet lake")
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_netease_search_large_page() -> Result<()> {
+        let server = FixtureServer::start_all().await;
+        let source = NeteaseLyricSource::with_base_url(server.url());
+        // offset 溢出时不能 panic
+        source
+            .search_page(TITLE, u32::MAX, MAX_SEARCH_LIMIT)
+            .await?;
+        let requests = server.requests();
+        assert!(
+            requests
+                .iter()
+                .any(|request| request.contains(&u32::MAX.to_string()))
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_netease_pure_music() -> Result<()> {
+        let server =
+            FixtureServer::start(vec![Fixture::json(NETEASE_LYRIC_PATH, NETEASE_PURE_MUSIC)]).await;
+        let source = NeteaseLyricSource::with_base_url(server.url());
+        let lyric = source.fetch_lyrics("1827600118").await?;
+        assert!(lyric.is_none());
+        assert!(lyric.instrumental);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_request_through_proxy() -> Result<()> {
+        // 代理收到的是完整地址, 本地服务按路径返回
+        let proxy = FixtureServer::start_all().await;
+        let config = SourceConfig {
+            proxy: Some(proxy.url().to_string()),
+            user_agent: Some("osu-lyric-test".to_string()),
+            headers: HashMap::from([(
+                "netease".to_string(),
+                HashMap::from([("X-Real-IP".to_string(), "1.2.3.4".to_string())]),
+            )]),
+            cookies: HashMap::from([("Netease".to_string(), "os=pc".to_string())]),
+        };
+        let source = NeteaseLyricSource::with_client(
+            "http://music.fixture.invalid",
+            build_client(&config)?,
+            source_headers(&config, "Netease"),
+        );
+        let songs = source.search_music(TITLE).await?;
+        assert_eq!(songs.len(), 1);
+
+        let requests = proxy.requests();
+        let request = requests.first().ok_or("proxy not used")?.to_lowercase();
+        assert!(request.starts_with("get http://music.fixture.invalid/search/get?"));
+        assert!(request.contains("user-agent: osu-lyric-test"));
+        assert!(request.contains("x-real-ip: 1.2.3.4"));
+        assert!(request.contains("cookie: os=pc"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_page() -> Result<()> {
+        let server = FixtureServer::start_all().await;
+        let qq = QQLyricSource::with_base_url(server.url());
+        let netease = NeteaseLyricSource::with_base_url(server.url());
+        qq.search_page("月光 & 夜曲", 2, 10).await?;
+        netease.search_page("月光 & 夜曲", 3, 20).await?;
+
+        let requests = server.requests();
+        let qq_request = requests[0].lines().next().unwrap_or_default();
+        assert!(qq_request.contains("p=2&n=10"));
+        // 关键字要经过编码, 不能截断 query
+        assert!(qq_request.contains("w=%E6%9C%88%E5%85%89+%26+"));
+        let netease_request = requests[1].lines().next().unwrap_or_default();
+        assert!(netease_request.contains("limit=20&offset=40"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_source_headers_only_for_source() {
+        let config = SourceConfig {
+            headers: HashMap::from([(
+                "QQ".to_string(),
+                HashMap::from([("Origin".to_string(), "https://y.qq.com".to_string())]),
+            )]),
+            ..Default::default()
+        };
+        assert_eq!(source_headers(&config, "QQ").len(), 1);
+        assert!(source_headers(&config, "Netease").is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_search_lyrics_filter_length() -> Result<()> {
+        let server = FixtureServer::start_all().await;
+        let source = QQLyricSource::with_base_url(server.url());
+        let song_info = source.search_all_music(TITLE, ARTIST).await?;
+        // 两首都在候选中, 只有时长接近的被采用
+        let lyric = source
+            .search_lyrics(&song_info, TITLE, 187_000, ARTIST)
+            .await?;
+        assert!(lyric.is_some());
+        let lyric = source
+            .search_lyrics(&song_info, TITLE, 600_000, ARTIST)
+            .await?;
+        assert!(lyric.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_lyrics_skip_failed_candidate() -> Result<()> {
+        // 第一个候选的歌词接口返回 404, 之后正常
+        let server = FixtureServer::start(vec![
+            Fixture::json(QQ_SEARCH_PATH, QQ_SEARCH),
+            Fixture::json(QQ_LYRIC_PATH, "").status(404).times(1),
+            Fixture::json(QQ_LYRIC_PATH, QQ_LYRIC),
+        ])
+        .await;
+        let source = QQLyricSource::with_base_url(server.url());
+        let song_info = source.search_all_music(TITLE, ARTIST).await?;
+        let (song, lyric) = source
+            .search_lyrics(&song_info, TITLE, 0, ARTIST)
+            .await?
+            .ok_or("没有换下一个候选")?;
+        assert_eq!(song.key, song_info[1].key);
+        assert!(lyric.lyric.is_some());
+
+        // 全部失败时返回错误
+        let server = FixtureServer::start(vec![Fixture::json(QQ_LYRIC_PATH, "").status(404)]).await;
+        let source = QQLyricSource::with_base_url(server.url());
+        let result = source.search_lyrics(&song_info, TITLE, 0, ARTIST).await;
+        assert!(result.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_search_lyrics_failed_and_invalid() -> Result<()> {
+        // 第一个候选获取失败, 第二个只有一行, 不能当作没有歌词
+        const SHORT_LYRIC: &str =
+            r#"{"retcode":0,"code":0,"subcode":0,"lyric":"[00:00.00]only line","trans":""}"#;
+        let server = FixtureServer::start(vec![
+            Fixture::json(QQ_SEARCH_PATH, QQ_SEARCH),
+            Fixture::json(QQ_LYRIC_PATH, "").status(404).times(1),
+            Fixture::json(QQ_LYRIC_PATH, SHORT_LYRIC),
+        ])
+        .await;
+        let source = QQLyricSource::with_base_url(server.url());
+        let song_info = source.search_all_music(TITLE, ARTIST).await?;
+        let result = source.search_lyrics(&song_info, TITLE, 0, ARTIST).await;
+        assert!(result.is_err());
+        // 第二个候选本身能获取到
+        let lyric = source.fetch_lyrics(&song_info[1].key).await?;
+        assert!(lyric.lyric.is_some());
         Ok(())
     }
 }
@@ -1,11 +1,22 @@
-use super::{CLIENT, LyricResult, LyricSource, SongInfo};
+use super::{
+    CLIENT, LyricResult, LyricSource, RequestPolicy, SongInfo, SourceGuard, build_client,
+    source_headers, year_of_timestamp,
+};
+use crate::config::{GLOBAL_CONFIG, SourceConfig};
 use crate::error::{Error, Result};
 use async_trait::async_trait;
+use reqwest::Client;
+use reqwest::header::HeaderMap;
 use serde::Deserialize;
 
 const NETEASE_API_BASE: &str = "https://music.163.com/api";
 
-pub struct NeteaseLyricSource;
+pub struct NeteaseLyricSource {
+    base_url: String,
+    client: Client,
+    headers: HeaderMap,
+    guard: SourceGuard,
+}
 
 // 歌曲搜索
 #[derive(Deserialize)]
@@ -26,6 +37,19 @@ struct NeteaseSong {
     name: String,
     artists: Vec<NeteaseArtist>,
     duration: u32,
+    #[serde(default)]
+    album: Option<NeteaseAlbum>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(rename_all = "camelCase")]
+struct NeteaseAlbum {
+    name: String,
+    #[serde(default)]
+    pic_url: Option<String>,
+    /// 毫秒
+    #[serde(default)]
+    publish_time: i64,
 }
 
 #[derive(Deserialize)]
@@ -44,6 +68,14 @@ struct NeteaseLyricResponse {
     lrc: Option<LyricItem>,
     #[serde(default)]
     tlyric: Option<LyricItem>,
+    #[serde(default)]
+    romalrc: Option<LyricItem>,
+    /// 旧版逐字歌词
+    #[serde(default)]
+    klyric: Option<LyricItem>,
+    /// 新版逐字歌词
+    #[serde(default)]
+    yrc: Option<LyricItem>,
 }
 
 #[derive(Deserialize)]
@@ -53,21 +85,41 @@ struct LyricItem {
 
 impl Default for NeteaseLyricSource {
     fn default() -> Self {
-        Self
+        Self::with_client(
+            NETEASE_API_BASE,
+            CLIENT.clone(),
+            source_headers(&GLOBAL_CONFIG.source, "Netease"),
+        )
     }
 }
 
 impl NeteaseLyricSource {
-    /// 构建根据标题搜索歌曲的 URL。
-    fn search_url(title: &str) -> String {
-        format!("{}/search/get?s={}&type=1&limit=5", NETEASE_API_BASE, title)
+    /// 使用指定的接口地址, 测试时可以指向本地服务。
+    pub fn with_base_url<T: Into<String>>(base_url: T) -> Self {
+        let client = build_client(&SourceConfig::default()).expect("cannot create client");
+        Self::with_client(base_url, client, HeaderMap::new())
+    }
+
+    /// `headers` 会附加在每个请求上, 同名时覆盖默认值
+    pub fn with_client<T: Into<String>>(base_url: T, client: Client, headers: HeaderMap) -> Self {
+        Self {
+            base_url: base_url.into(),
+            client,
+            headers,
+            guard: SourceGuard::new("Netease", RequestPolicy::default()),
+        }
+    }
+
+    /// 构建搜索歌曲的 URL, 参数通过 query 传入。
+    fn search_url(&self) -> String {
+        format!("{}/search/get", self.base_url)
     }
 
     /// 构建根据歌曲 ID 获取歌词的 URL。
-    fn lyric_url(song_id: &str) -> String {
+    fn lyric_url(&self, song_id: &str) -> String {
         format!(
-            "{}/song/lyric?id={}&lv=1&kv=1&tv=-1",
-            NETEASE_API_BASE, song_id
+            "{}/song/lyric?id={}&lv=1&kv=1&tv=-1&rv=-1&yv=1",
+            self.base_url, song_id
         )
     }
 }
@@ -78,9 +130,28 @@ impl LyricSource for NeteaseLyricSource {
         "Netease"
     }
 
-    async fn search_music(&self, title: &str) -> Result<Vec<SongInfo>> {
-        let url = Self::search_url(title);
-        let response = CLIENT.get(&url).send().await?;
+    fn guard(&self) -> &SourceGuard {
+        &self.guard
+    }
+
+    async fn search_page(&self, keyword: &str, page: u32, limit: u32) -> Result<Vec<SongInfo>> {
+        let url = self.search_url();
+        let offset = page.saturating_sub(1).saturating_mul(limit);
+        let query = [
+            ("s", keyword.to_string()),
+            ("type", "1".to_string()),
+            ("limit", limit.to_string()),
+            ("offset", offset.to_string()),
+        ];
+        let response = self
+            .guard
+            .send(|| {
+                self.client
+                    .get(&url)
+                    .query(&query)
+                    .headers(self.headers.clone())
+            })
+            .await?;
 
         if !response.status().is_success() {
             return Ok(vec![]);
@@ -98,16 +169,24 @@ impl LyricSource for NeteaseLyricSource {
             .songs
             .unwrap_or_default()
             .into_iter()
-            .map(|song| SongInfo {
-                title: song.name,
-                artist: song
-                    .artists
-                    .into_iter()
-                    .map(|a| a.name)
-                    .collect::<Vec<_>>()
-                    .join(", "),
-                length: song.duration,
-                key: song.id.to_string(),
+            .map(|song| {
+                let album = song.album.unwrap_or_default();
+                SongInfo {
+                    title: song.name,
+                    artist: song
+                        .artists
+                        .into_iter()
+                        .map(|a| a.name)
+                        .collect::<Vec<_>>()
+                        .join(", "),
+                    length: song.duration,
+                    key: song.id.to_string(),
+                    album: Some(album.name).filter(|s| !s.is_empty()),
+                    cover: album.pic_url.filter(|s| !s.is_empty()),
+                    source_id: Some(song.id.to_string()),
+                    year: year_of_timestamp(album.publish_time / 1000),
+                    ..Default::default()
+                }
             })
             .collect();
         Ok(song_list)
@@ -115,8 +194,11 @@ impl LyricSource for NeteaseLyricSource {
 
     /// 获取指定网易云歌曲 ID 的原文和翻译歌词。
     async fn fetch_lyrics(&self, song_id: &str) -> Result<LyricResult> {
-        let url = Self::lyric_url(song_id);
-        let response = CLIENT.get(&url).send().await?;
+        let url = self.lyric_url(song_id);
+        let response = self
+            .guard
+            .send(|| self.client.get(&url).headers(self.headers.clone()))
+            .await?;
 
         if !response.status().is_success() {
             return Err(Error::Runtime(format!(
@@ -134,17 +216,26 @@ impl LyricSource for NeteaseLyricSource {
 
         if result.pure_music {
             return Ok(LyricResult {
-                lyric: None,
-                trans: None,
+                instrumental: true,
+                ..Default::default()
             });
         }
 
-        let lyric = result.lrc.map(|item| item.lyric).filter(|s| !s.is_empty());
+        fn non_empty(item: Option<LyricItem>) -> Option<String> {
+            item.map(|item| item.lyric).filter(|s| !s.trim().is_empty())
+        }
 
-        let trans = result
-            .tlyric
-            .map(|item| item.lyric)
-            .filter(|s| !s.is_empty());
-        Ok(LyricResult { lyric, trans })
+        let word_timing = result
+            .klyric
+            .is_some_and(|item| !item.lyric.trim().is_empty())
+            || result.yrc.is_some_and(|item| !item.lyric.trim().is_empty());
+        Ok(LyricResult {
+            lyric: non_empty(result.lrc),
+            trans: non_empty(result.tlyric),
+            roma: non_empty(result.romalrc),
+            word_timing,
+            extras_known: true,
+            instrumental: false,
+        })
     }
 }
@@ -1,9 +1,23 @@
-use super::{CLIENT, LyricResult, LyricSource, SongInfo};
+use super::{
+    CLIENT, LyricResult, LyricSource, RequestPolicy, SongInfo, SourceGuard, build_client,
+    source_headers, year_of_timestamp,
+};
+use crate::config::{GLOBAL_CONFIG, SourceConfig};
 use crate::error::Result;
 use async_trait::async_trait;
+use reqwest::Client;
+use reqwest::header::HeaderMap;
 use serde::Deserialize;
 
-pub struct QQLyricSource;
+const QQ_API_BASE: &str = "https://c.y.qq.com";
+const QQ_COVER_BASE: &str = "https://y.gtimg.cn/music/photo_new";
+
+pub struct QQLyricSource {
+    base_url: String,
+    client: Client,
+    headers: HeaderMap,
+    guard: SourceGuard,
+}
 
 // 歌曲搜索
 #[derive(Deserialize)]
@@ -28,6 +42,15 @@ struct QQSong {
     singer: Vec<Singer>,
     interval: u32,
     songmid: String,
+    #[serde(default)]
+    songid: u64,
+    #[serde(default)]
+    albumname: String,
+    #[serde(default)]
+    albummid: String,
+    /// 秒
+    #[serde(default)]
+    pubtime: i64,
 }
 
 #[derive(Deserialize)]
@@ -45,22 +68,39 @@ struct QQLyricResponse {
 
 impl Default for QQLyricSource {
     fn default() -> Self {
-        Self
+        Self::with_client(
+            QQ_API_BASE,
+            CLIENT.clone(),
+            source_headers(&GLOBAL_CONFIG.source, "QQ"),
+        )
     }
 }
 
 impl QQLyricSource {
-    fn search_url(title: &str) -> String {
-        format!(
-            "https://c.y.qq.com/soso/fcgi-bin/client_search_cp?p=1&n=5&format=json&w={}",
-            title
-        )
+    /// 使用指定的接口地址, 测试时可以指向本地服务
+    pub fn with_base_url<T: Into<String>>(base_url: T) -> Self {
+        let client = build_client(&SourceConfig::default()).expect("cannot create client");
+        Self::with_client(base_url, client, HeaderMap::new())
+    }
+
+    /// `headers` 会附加在每个请求上, 同名时覆盖默认值
+    pub fn with_client<T: Into<String>>(base_url: T, client: Client, headers: HeaderMap) -> Self {
+        Self {
+            base_url: base_url.into(),
+            client,
+            headers,
+            guard: SourceGuard::new("QQ", RequestPolicy::default()),
+        }
+    }
+
+    fn search_url(&self) -> String {
+        format!("{}/soso/fcgi-bin/client_search_cp", self.base_url)
     }
 
-    fn lyric_url(song_id: &str) -> String {
+    fn lyric_url(&self, song_id: &str) -> String {
         format!(
-            "https://c.y.qq.com/lyric/fcgi-bin/fcg_query_lyric_new.fcg?songmid={}&format=json&nobase64=1",
-            song_id
+            "{}/lyric/fcgi-bin/fcg_query_lyric_new.fcg?songmid={}&format=json&nobase64=1",
+            self.base_url, song_id
         )
     }
 }
@@ -71,12 +111,27 @@ impl LyricSource for QQLyricSource {
         "QQ"
     }
 
-    async fn search_music(&self, title: &str) -> Result<Vec<SongInfo>> {
-        let url = Self::search_url(title);
-        let result = CLIENT
-            .get(&url)
-            .header("Referer", "https://y.qq.com/portal/player.html")
-            .send()
+    fn guard(&self) -> &SourceGuard {
+        &self.guard
+    }
+
+    async fn search_page(&self, keyword: &str, page: u32, limit: u32) -> Result<Vec<SongInfo>> {
+        let url = self.search_url();
+        let query = [
+            ("p", page.to_string()),
+            ("n", limit.to_string()),
+            ("format", "json".to_string()),
+            ("w", keyword.to_string()),
+        ];
+        let result = self
+            .guard
+            .send(|| {
+                self.client
+                    .get(&url)
+                    .query(&query)
+                    .header("Referer", "https://y.qq.com/portal/player.html")
+                    .headers(self.headers.clone())
+            })
             .await?
             .json::<QQResponse>()
             .await?;
@@ -101,17 +156,30 @@ impl LyricSource for QQLyricSource {
                 // 这里腾讯给的是秒数, 转换一下
                 length: song.interval * 1000,
                 key: song.songmid,
+                album: Some(song.albumname).filter(|s| !s.is_empty()),
+                cover: Some(song.albummid)
+                    .filter(|s| !s.is_empty())
+                    .map(|mid| format!("{QQ_COVER_BASE}/T002R300x300M000{mid}.jpg")),
+                source_id: Some(song.songid)
+                    .filter(|&id| id != 0)
+                    .map(|id| id.to_string()),
+                year: year_of_timestamp(song.pubtime),
+                ..Default::default()
             })
             .collect();
         Ok(song_list)
     }
 
     async fn fetch_lyrics(&self, song_id: &str) -> Result<LyricResult> {
-        let url = Self::lyric_url(song_id);
-        let result: QQLyricResponse = CLIENT
-            .get(&url)
-            .header("Referer", "https://y.qq.com/portal/player.html")
-            .send()
+        let url = self.lyric_url(song_id);
+        let result: QQLyricResponse = self
+            .guard
+            .send(|| {
+                self.client
+                    .get(&url)
+                    .header("Referer", "https://y.qq.com/portal/player.html")
+                    .headers(self.headers.clone())
+            })
             .await?
             .json()
             .await?;
@@ -128,6 +196,11 @@ impl LyricSource for QQLyricSource {
             Some(result.trans)
         };
 
-        Ok(LyricResult { lyric, trans })
+        // 这个接口不提供罗马音与逐字歌词
+        Ok(LyricResult {
+            lyric,
+            trans,
+            ..Default::default()
+        })
     }
 }
@@ -0,0 +1,116 @@
+//! 点击打轴
+//!
+//! 先提交没有时间的歌词, 播放时每行开始时点击一次, 按点击的时间生成歌词
+
+use super::{Lyric, LyricLine};
+use crate::error::Result;
+
+pub struct TapRecorder {
+    /// (原文, 翻译)
+    lines: Vec<(String, Option<String>)>,
+    /// 已点击的行的时间, 秒
+    times: Vec<f32>,
+}
+
+impl TapRecorder {
+    /// 按行拆分, 忽略空行; 翻译按行号对应原文
+    pub fn new(text: &str, trans_text: Option<&str>) -> Result<Self> {
+        let split = |text: &str| {
+            text.lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty())
+                .map(str::to_string)
+                .collect::<Vec<_>>()
+        };
+        let mut translations = trans_text.map(split).unwrap_or_default().into_iter();
+        let lines = split(text)
+            .into_iter()
+            .map(|line| (line, translations.next()))
+            .collect::<Vec<_>>();
+        if lines.is_empty() {
+            return Err("lyric is empty".into());
+        }
+        Ok(Self {
+            lines,
+            times: Vec::new(),
+        })
+    }
+
+    /// 记下下一行的开始时间, 返回这一行的行号
+    pub fn tap(&mut self, time: f32) -> Result<usize> {
+        let index = self.times.len();
+        if index >= self.lines.len() {
+            return Err("所有行都已打轴".into());
+        }
+        self.times.push(time.max(0f32));
+        Ok(index)
+    }
+
+    /// 撤销最后一次点击, 返回撤销的行号
+    pub fn undo(&mut self) -> Option<usize> {
+        self.times.pop().map(|_| self.times.len())
+    }
+
+    pub fn total(&self) -> usize {
+        self.lines.len()
+    }
+
+    /// 下一次点击对应的行
+    pub fn next_line(&self) -> Option<&str> {
+        self.lines
+            .get(self.times.len())
+            .map(|(line, _)| line.as_str())
+    }
+
+    /// 已打轴的行
+    pub fn tapped_lines(&self) -> Vec<LyricLine> {
+        self.lines
+            .iter()
+            .zip(&self.times)
+            .map(|((origin, translation), time)| LyricLine {
+                time: *time,
+                origin: Some(origin.clone()),
+                translation: translation.clone(),
+            })
+            .collect()
+    }
+
+    /// 用已打轴的行生成歌词, 没打轴的行丢弃
+    pub fn to_lyric(&self) -> Result<Lyric> {
+        if self.times.is_empty() {
+            return Err("还没有打轴".into());
+        }
+        Lyric::from_lines(self.tapped_lines())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tap_recorder() -> Result<()> {
+        let mut recorder = TapRecorder::new("第一行\n\n第二行\n第三行\n", Some("first\nsecond"))?;
+        assert_eq!(recorder.total(), 3);
+        assert!(recorder.to_lyric().is_err());
+
+        assert_eq!(recorder.tap(1.5)?, 0);
+        assert_eq!(recorder.tap(4.0)?, 1);
+        assert_eq!(recorder.undo(), Some(1));
+        assert_eq!(recorder.next_line(), Some("第二行"));
+        assert_eq!(recorder.tap(3.0)?, 1);
+
+        let lyric = recorder.to_lyric()?;
+        let lines = lyric.get_lyrics();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1].time, 3.0);
+        assert_eq!(lines[1].translation.as_deref(), Some("second"));
+        assert_eq!(lyric.end_time(), 3.0);
+
+        recorder.tap(6.0)?;
+        assert_eq!(recorder.next_line(), None);
+        assert!(recorder.tap(7.0).is_err());
+        assert!(TapRecorder::new(" \n", None).is_err());
+        Ok(())
+    }
+}
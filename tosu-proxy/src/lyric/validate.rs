@@ -0,0 +1,146 @@
+//! 对获取到的歌词做简单的合理性检查, 不通过时换下一个候选
+
+use super::{Lyric, LyricLine};
+use crate::error::{Error, Result};
+
+/// 去掉制作信息后至少需要的行数
+const MIN_LINES: usize = 4;
+/// 每分钟至少需要的行数, 只在知道音频时长时检查
+const MIN_LINES_PER_MINUTE: f32 = 0.8;
+/// 最后一行允许超出音频时长的范围, 毫秒
+const END_TOLERANCE: u32 = 15000;
+
+/// 只有这些内容的行视为制作信息
+const CREDIT_KEYWORDS: &[&str] = &[
+    "作词",
+    "作曲",
+    "编曲",
+    "词",
+    "曲",
+    "制作人",
+    "监制",
+    "混音",
+    "母带",
+    "录音",
+    "和声",
+    "吉他",
+    "贝斯",
+    "鼓",
+    "弦乐",
+    "出品",
+    "发行",
+    "OP",
+    "SP",
+    "lyricist",
+    "lyrics",
+    "composer",
+    "arranger",
+    "producer",
+    "mixing",
+    "mastering",
+    "vocal",
+    "lyrics by",
+    "composed by",
+    "arranged by",
+    "written by",
+];
+
+/// `length` 为音频时长, 毫秒, 为 0 时不检查时长相关的项
+pub fn validate_lyric(lyric: &Lyric, length: u32) -> Result<()> {
+    let lines = lyric
+        .get_lyrics()
+        .iter()
+        .filter(|line| is_content_line(line))
+        .collect::<Vec<_>>();
+
+    if lines.len() < MIN_LINES {
+        return Err(Error::Runtime(format!(
+            "歌词只有 {} 行有效内容",
+            lines.len()
+        )));
+    }
+
+    if length == 0 {
+        return Ok(());
+    }
+
+    let last_time = lines.last().map_or(0, |line| (line.time * 1000f32) as u32);
+    if last_time > length + END_TOLERANCE {
+        return Err(Error::Runtime(format!(
+            "歌词结束于 {}s, 超出音频时长 {}s",
+            last_time / 1000,
+            length / 1000
+        )));
+    }
+
+    let minutes = length as f32 / 60000f32;
+    if (lines.len() as f32) < minutes * MIN_LINES_PER_MINUTE {
+        return Err(Error::Runtime(format!(
+            "歌词过于稀疏: {} 行 / {:.1} 分钟",
+            lines.len(),
+            minutes
+        )));
+    }
+    Ok(())
+}
+
+fn is_content_line(line: &LyricLine) -> bool {
+    let Some(text) = line.origin.as_deref().or(line.translation.as_deref()) else {
+        return false;
+    };
+    let text = text.trim();
+    !text.is_empty() && !is_credit(text)
+}
+
+/// `作词 : xxx` / `Composer: xxx` 这样的行
+fn is_credit(text: &str) -> bool {
+    let Some((key, _)) = text.split_once([':', '：']) else {
+        return false;
+    };
+    let key = key.trim();
+    CREDIT_KEYWORDS
+        .iter()
+        .any(|keyword| key.eq_ignore_ascii_case(keyword))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LYRIC: &str = "[00:00.00]Clair de lune - Claude Debussy\n[00:12.50]月光落在安静的湖面\n[00:25.30]风吹过窗前的琴键\n[00:38.10]夜色慢慢变得温柔\n[00:51.75]梦里还有那首旋律\n[01:05.40]等待黎明悄悄来临\n";
+
+    #[test]
+    fn test_validate_ok() -> Result<()> {
+        let lyric = Lyric::parse(LYRIC, None, None)?;
+        validate_lyric(&lyric, 80_000)?;
+        validate_lyric(&lyric, 0)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_too_few_lines() -> Result<()> {
+        let lyric = Lyric::parse(
+            "[00:00.00]作词 : Fixture Writer\n[00:01.00]作曲：Claude Debussy\n[00:02.00]Arranger: Someone\n[00:12.50]月光落在安静的湖面\n[00:25.30]风吹过窗前的琴键\n",
+            None,
+            None,
+        )?;
+        assert!(validate_lyric(&lyric, 0).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_exceed_length() -> Result<()> {
+        let lyric = Lyric::parse(LYRIC, None, None)?;
+        // 最后一行在 65 秒
+        assert!(validate_lyric(&lyric, 40_000).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_validate_too_sparse() -> Result<()> {
+        let lyric = Lyric::parse(LYRIC, None, None)?;
+        // 10 分钟只有 6 行
+        assert!(validate_lyric(&lyric, 600_000).is_err());
+        Ok(())
+    }
+}
@@ -26,11 +26,10 @@ pub struct LyricSetting {
 /// }
 ///
 /// ```
-
 #[cfg(test)]
 mod test {
     use super::{LyricSetting, LyricSettingType};
-    use crate::database::init_database;
+    use crate::database::init_test_database;
     use crate::error::Result;
     use crate::model::JsonStruct;
     use crate::model::websocket::setting::SettingPayload;
@@ -63,7 +62,7 @@ mod test {
     #[tokio::test]
     async fn test_default() -> Result<()> {
         init();
-        init_database().await;
+        init_test_database().await;
         let setting = LyricSetting::init().await;
         println!("{setting:?}");
         Ok(())
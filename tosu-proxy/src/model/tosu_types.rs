@@ -119,6 +119,13 @@ pub struct Folders {
     pub beatmap: String,
 }
 
+// tosu 的 state.number, 与 osu! 的 OsuModes 一致
+const STATE_PLAY: i64 = 2;
+const STATE_SELECT_EDIT: i64 = 4;
+const STATE_SELECT_PLAY: i64 = 5;
+const STATE_RESULTS: i64 = 7;
+const STATE_SELECT_MULTI: i64 = 13;
+
 /// Tosu API 响应的主结构
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
@@ -131,9 +138,29 @@ pub struct TosuApi {
 
     /// 文件夹路径
     pub folders: Folders,
+
+    /// 游戏状态, 旧版本 tosu 没有
+    #[serde(default)]
+    pub state: Option<NumberName>,
 }
 
 impl TosuApi {
+    /// 是否在选歌界面 (单人 / 编辑器 / 多人)
+    pub fn is_song_select(&self) -> bool {
+        matches!(
+            self.state.as_ref().map(|state| state.number),
+            Some(STATE_SELECT_EDIT | STATE_SELECT_PLAY | STATE_SELECT_MULTI)
+        )
+    }
+
+    /// 是否在游玩或成绩界面
+    pub fn is_playing(&self) -> bool {
+        matches!(
+            self.state.as_ref().map(|state| state.number),
+            Some(STATE_PLAY | STATE_RESULTS)
+        )
+    }
+
     /// 获取音频文件的完整路径
     pub fn audio_path(&self) -> PathBuf {
         Path::new(&self.folders.songs)
@@ -0,0 +1,19 @@
+use crate::lyric::SongInfoKey;
+use serde::{Deserialize, Serialize};
+
+/// 为任意谱面指定歌词, `lyric` 和 `text` 二选一
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AssignLyricRequest {
+    pub bid: u32,
+    /// 没有缓存过的谱面需要提供
+    pub sid: Option<u32>,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    /// 从歌词源获取
+    pub lyric: Option<SongInfoKey>,
+    /// 上传的 lrc 文本
+    pub text: Option<String>,
+    /// 上传的翻译 lrc 文本, 只和 `text` 一起使用
+    pub trans_text: Option<String>,
+}
@@ -6,3 +6,68 @@ pub struct BlockItem {
     pub sid: u32,
     pub title: String,
 }
+
+/// 拉黑规则的范围, 声明顺序即优先级, 越具体越优先
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum BlockScope {
+    Bid,
+    Sid,
+    /// 标题完全一致, 忽略大小写
+    Title,
+    /// 艺术家完全一致, 忽略大小写
+    Artist,
+    /// 标题匹配正则
+    Pattern,
+}
+
+impl BlockScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BlockScope::Bid => "bid",
+            BlockScope::Sid => "sid",
+            BlockScope::Title => "title",
+            BlockScope::Artist => "artist",
+            BlockScope::Pattern => "pattern",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            BlockScope::Bid,
+            BlockScope::Sid,
+            BlockScope::Title,
+            BlockScope::Artist,
+            BlockScope::Pattern,
+        ]
+        .into_iter()
+        .find(|scope| scope.as_str() == name)
+    }
+}
+
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockRule {
+    pub id: i32,
+    pub scope: BlockScope,
+    pub value: String,
+    /// `false` 表示例外, 范围更大的规则拉黑时仍然显示歌词
+    pub block: bool,
+    /// 秒级时间戳
+    pub created_at: i64,
+}
+
+/// 新增拉黑规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BlockRuleRequest {
+    pub scope: BlockScope,
+    /// bid/sid 为数字, `pattern` 为正则
+    pub value: String,
+    #[serde(default = "default_block")]
+    pub block: bool,
+}
+
+fn default_block() -> bool {
+    true
+}
@@ -0,0 +1,66 @@
+use super::lyric_info::LyricInfo;
+use serde::{Deserialize, Serialize};
+
+/// 列出/搜索歌词缓存的请求, 条件都不填时列出全部
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheQuery {
+    /// 标题包含的文本
+    pub title: Option<String>,
+    /// 艺术家包含的文本
+    pub artist: Option<String>,
+    pub bid: Option<u32>,
+    /// 从 1 开始
+    #[serde(default = "default_page")]
+    pub page: u32,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+impl Default for CacheQuery {
+    fn default() -> Self {
+        Self {
+            title: None,
+            artist: None,
+            bid: None,
+            page: default_page(),
+            limit: default_limit(),
+        }
+    }
+}
+
+fn default_page() -> u32 {
+    1
+}
+
+fn default_limit() -> u32 {
+    20
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheListResult {
+    pub page: u32,
+    /// 符合条件的总数
+    pub total: u64,
+    pub items: Vec<LyricInfo>,
+}
+
+/// 删除缓存, 传 `bid` 删除一张谱面, 只传 `sid` 删除整个谱面集
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CacheKey {
+    pub bid: Option<u32>,
+    pub sid: Option<u32>,
+    /// 同时删除固定的缓存
+    #[serde(default)]
+    pub force: bool,
+}
+
+/// 固定或取消固定一条缓存
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CachePin {
+    pub bid: u32,
+    pub pinned: bool,
+}
@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+/// 修改当前歌词的一行, 不填的字段不修改, 空字符串表示清除
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricLineEdit {
+    pub index: usize,
+    /// 秒
+    pub time: Option<f32>,
+    pub origin: Option<String>,
+    pub translation: Option<String>,
+}
+
+/// 按时间插入一行
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricLineInsert {
+    /// 秒
+    pub time: f32,
+    pub origin: Option<String>,
+    pub translation: Option<String>,
+}
+
+/// 从 `start` 开始的 `count` 行, 不填 `count` 时删除一行/移动到最后一行
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricLineRange {
+    pub start: usize,
+    pub count: Option<usize>,
+    /// 移动的时间, 毫秒, 只用于 `shiftLyricLines`
+    #[serde(default)]
+    pub offset: i32,
+}
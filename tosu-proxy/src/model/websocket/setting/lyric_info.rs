@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+
+/// 歌词缓存的来源
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricInfo {
+    pub bid: u32,
+    pub sid: u32,
+    pub title: String,
+    /// 旧版本的缓存没有记录
+    pub artist: Option<String>,
+    /// `QQ`/`Netease`, 旧版本的缓存没有记录
+    pub source: Option<String>,
+    pub source_key: Option<String>,
+    /// 翻译来源, 与歌词来源不同时说明是合并来的
+    pub trans_source: Option<String>,
+    pub trans_source_key: Option<String>,
+    /// 获取歌词的时间, 秒级时间戳
+    pub fetched_at: Option<i64>,
+    /// 是否为手动选择的歌词
+    pub manual: bool,
+    /// 是否固定, 固定的缓存不会过期, 也不会被批量清除
+    pub pinned: bool,
+}
@@ -0,0 +1,20 @@
+use serde::{Deserialize, Serialize};
+
+/// 一次同步点: 这个播放时间应该显示这个歌词时间, 毫秒
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SyncAnchor {
+    pub play_time: i32,
+    pub lyric_time: i32,
+}
+
+/// 当前歌曲的时间校正, 歌词时间 = 播放时间 * scale + offset + 全局和歌词源的偏移
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricSync {
+    /// 谱面的偏移, 毫秒
+    pub offset: i32,
+    pub scale: f64,
+    /// 下一个同步点会和它一起计算倍率
+    pub anchor: Option<SyncAnchor>,
+}
@@ -0,0 +1,16 @@
+use serde::{Deserialize, Serialize};
+
+/// 没有找到歌词的谱面
+#[derive(PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MissItem {
+    pub bid: u32,
+    pub sid: u32,
+    pub title: String,
+    /// 歌词源标记为纯音乐
+    pub instrumental: bool,
+    /// 搜索时间, 秒级时间戳
+    pub checked_at: i64,
+    /// 到这个时间之后会重新搜索, 秒级时间戳
+    pub retry_at: i64,
+}
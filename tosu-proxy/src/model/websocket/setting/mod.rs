@@ -1,6 +1,18 @@
+pub mod assign;
 pub mod base;
 pub mod block;
+pub mod cache;
+pub mod lyric_edit;
+pub mod lyric_info;
+pub mod lyric_sync;
+pub mod miss;
+pub mod offset;
+pub mod precache;
+pub mod preview;
+pub mod search;
+pub mod search_override;
 pub mod song_info;
+pub mod tap_sync;
 
 use crate::error::Result;
 use crate::model::JsonStruct;
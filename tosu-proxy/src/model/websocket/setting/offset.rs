@@ -0,0 +1,99 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// 修改指定谱面的偏移
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsetRequest {
+    pub bid: u32,
+    /// 毫秒
+    pub offset: i32,
+}
+
+/// `setLyricOffset` 的值, 只传数字时修改当前歌曲
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum OffsetValue {
+    Current(i32),
+    Beatmap(OffsetRequest),
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsetItem {
+    pub bid: u32,
+    pub sid: u32,
+    pub title: String,
+    /// 毫秒
+    pub offset: i32,
+    /// 见 [`super::lyric_sync::LyricSync`]
+    pub scale: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsetQuery {
+    /// 从 1 开始
+    #[serde(default = "default_page")]
+    pub page: u32,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+impl Default for OffsetQuery {
+    fn default() -> Self {
+        Self {
+            page: default_page(),
+            limit: default_limit(),
+        }
+    }
+}
+
+fn default_page() -> u32 {
+    1
+}
+
+fn default_limit() -> u32 {
+    20
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsetListResult {
+    pub page: u32,
+    pub total: u64,
+    pub items: Vec<OffsetItem>,
+}
+
+/// 全局和按歌词源的偏移, 都和谱面的偏移相加
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OffsetSetting {
+    /// 毫秒, 例如直播的延迟
+    #[serde(default)]
+    pub global: i32,
+    /// 歌词源名称 -> 毫秒
+    #[serde(default)]
+    pub sources: BTreeMap<String, i32>,
+}
+
+/// 修改一个歌词源的偏移, 为 0 时删除
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SourceOffsetRequest {
+    pub source: String,
+    /// 毫秒
+    pub offset: i32,
+}
+
+/// 当前歌词实际使用的偏移, `total = global + source_offset + beatmap`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricOffset {
+    pub total: i32,
+    pub global: i32,
+    /// 当前歌词的来源, 手动上传等没有来源时为空
+    pub source: Option<String>,
+    pub source_offset: i32,
+    pub beatmap: i32,
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+
+/// 开始批量预缓存
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrecacheRequest {
+    /// Songs 目录, 不填时使用 tosu 上报的目录
+    pub path: Option<String>,
+    /// 两次搜索之间的间隔, 毫秒
+    pub interval: Option<u64>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum PrecacheState {
+    #[default]
+    Idle,
+    /// 正在遍历 Songs 目录
+    Scanning,
+    Running,
+    Paused,
+    Finished,
+    Cancelled,
+    Failed,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrecacheFailure {
+    pub bid: i64,
+    pub sid: i64,
+    pub title: String,
+    pub error: String,
+}
+
+/// 批量预缓存的进度
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PrecacheStatus {
+    pub state: PrecacheState,
+    /// 谱面集数量
+    pub total: usize,
+    /// 已处理的数量, 包含下面所有情况
+    pub done: usize,
+    /// 新写入缓存
+    pub saved: usize,
+    /// 已有缓存或已拉黑
+    pub skipped: usize,
+    pub not_found: usize,
+    pub failed: usize,
+    /// 正在处理的标题
+    pub current: Option<String>,
+    /// 任务本身的错误, 例如目录无法读取
+    pub error: Option<String>,
+    /// 最近的失败记录
+    pub failures: Vec<PrecacheFailure>,
+}
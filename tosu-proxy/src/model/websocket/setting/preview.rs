@@ -0,0 +1,28 @@
+use crate::lyric::{Lyric, LyricLine};
+use serde::{Deserialize, Serialize};
+
+/// 候选歌词的预览, 只返回给请求方
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LyricPreview {
+    pub lines: Vec<LyricLine>,
+    pub line_count: usize,
+    /// 第一行的时间, 毫秒
+    pub start_time: i32,
+    /// 最后一行的时间, 毫秒
+    pub end_time: i32,
+    pub has_translation: bool,
+}
+
+impl From<&Lyric> for LyricPreview {
+    fn from(lyric: &Lyric) -> Self {
+        let lines = lyric.get_lyrics().to_vec();
+        Self {
+            line_count: lines.len(),
+            lines,
+            start_time: (lyric.start_time() * 1000f32) as i32,
+            end_time: (lyric.end_time() * 1000f32) as i32,
+            has_translation: lyric.has_translation(),
+        }
+    }
+}
@@ -0,0 +1,33 @@
+use crate::lyric::SongInfo;
+use serde::{Deserialize, Serialize};
+
+/// 手动搜索的请求
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchLyricRequest {
+    pub keyword: String,
+    /// `QQ`/`Netease`
+    pub source: String,
+    /// 从 1 开始
+    #[serde(default = "default_page")]
+    pub page: u32,
+    #[serde(default = "default_limit")]
+    pub limit: u32,
+}
+
+fn default_page() -> u32 {
+    1
+}
+
+fn default_limit() -> u32 {
+    10
+}
+
+/// 手动搜索的结果
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchLyricResult {
+    pub source: String,
+    pub page: u32,
+    pub songs: Vec<SongInfo>,
+}
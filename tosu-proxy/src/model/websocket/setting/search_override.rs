@@ -0,0 +1,33 @@
+use serde::{Deserialize, Serialize};
+
+/// 修改谱面搜索歌词时使用的标题/艺术家, 只传 `sid` 时对整个谱面集生效
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchOverrideRequest {
+    pub bid: Option<u32>,
+    pub sid: Option<u32>,
+    /// 不填时使用谱面的标题
+    pub title: Option<String>,
+    /// 不填时使用谱面的艺术家(或别名)
+    pub artist: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SearchOverride {
+    pub id: i32,
+    /// 0 表示整个谱面集
+    pub bid: u32,
+    pub sid: u32,
+    pub title: Option<String>,
+    pub artist: Option<String>,
+}
+
+/// 艺术家别名, 搜索时用 `alias` 代替谱面中的 `artist`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ArtistAlias {
+    /// 匹配时忽略大小写
+    pub artist: String,
+    pub alias: String,
+}
@@ -0,0 +1,23 @@
+use crate::lyric::LyricLine;
+use serde::{Deserialize, Serialize};
+
+/// 开始点击打轴, 每行一句, 空行忽略
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TapSyncRequest {
+    pub text: String,
+    /// 翻译, 按行号对应原文
+    pub trans_text: Option<String>,
+}
+
+/// 打轴进度
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TapSyncStatus {
+    pub bid: u32,
+    pub total: usize,
+    /// 已打轴的行, 带时间(秒)
+    pub lines: Vec<LyricLine>,
+    /// 下一次点击对应的行, 全部打完时为 `None`
+    pub next: Option<String>,
+}
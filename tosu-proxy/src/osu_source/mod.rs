@@ -1,8 +1,27 @@
+mod osu_file;
 mod tosu;
 
+pub use osu_file::{OsuFileMeta, OsuSetEntry, scan_songs_folder};
+use std::path::PathBuf;
+use std::sync::RwLock;
 pub use tosu::TosuWebsocketClient;
+use tracing::error;
 
-#[derive(Debug)]
+static SONGS_FOLDER: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// tosu 上报的 Songs 目录, 还没连接过 tosu 时为 `None`
+pub fn songs_folder() -> Option<PathBuf> {
+    SONGS_FOLDER.read().unwrap().clone()
+}
+
+fn set_songs_folder(path: &str) {
+    if path.is_empty() || SONGS_FOLDER.read().unwrap().as_deref() == Some(path.as_ref()) {
+        return;
+    }
+    *SONGS_FOLDER.write().unwrap() = Some(PathBuf::from(path));
+}
+
+#[derive(Debug, Clone)]
 pub struct OsuSongInfo {
     pub bid: i64,
     pub sid: i64,
@@ -15,6 +34,28 @@ pub struct OsuSongInfo {
     pub artist_unicode: String,
     pub title: String,
     pub title_unicode: String,
+    /// 在选歌界面浏览, 还没开始游玩
+    pub browsing: bool,
+    /// 音频文件内容的哈希, 相同音频的谱面集共用歌词, 还没计算或读取失败时为 `None`
+    pub audio_hash: Option<String>,
+    /// 音频文件, 需要查共享歌词时才用它计算哈希
+    pub audio_path: Option<PathBuf>,
+}
+
+impl OsuSongInfo {
+    /// 还没有哈希时从 `audio_path` 计算, 同一个文件没修改过时直接用上次的结果
+    pub async fn load_audio_hash(&mut self) {
+        let Some(path) = &self.audio_path else {
+            return;
+        };
+        if self.audio_hash.is_some() {
+            return;
+        }
+        self.audio_hash = crate::util::hash_audio_cached(path)
+            .await
+            .inspect_err(|e| error!("无法读取音频文件: {}", e))
+            .ok();
+    }
 }
 
 pub enum OsuState {
@@ -0,0 +1,186 @@
+//! 读取 `.osu` 文件中的谱面信息, 用于批量预缓存
+
+use super::OsuSongInfo;
+use std::path::{Path, PathBuf};
+
+/// `.osu` 文件 `[General]` / `[Metadata]` 中需要的字段
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OsuFileMeta {
+    pub audio_filename: String,
+    pub title: String,
+    pub title_unicode: String,
+    pub artist: String,
+    pub artist_unicode: String,
+    /// 旧版本的谱面没有, 为 0
+    pub bid: i64,
+    pub sid: i64,
+}
+
+impl OsuFileMeta {
+    /// 标题为空时返回 `None`
+    pub fn parse(text: &str) -> Option<Self> {
+        let mut meta = Self::default();
+        let mut section = "";
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with("//") {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                // 需要的字段都在 [Metadata] 之前, 后面的物件数据很长, 不用再读
+                if section == "Metadata" {
+                    break;
+                }
+                section = &line[1..line.len() - 1];
+                continue;
+            }
+            let Some((key, value)) = line.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match (section, key.trim()) {
+                ("General", "AudioFilename") => meta.audio_filename = value,
+                ("Metadata", "Title") => meta.title = value,
+                ("Metadata", "TitleUnicode") => meta.title_unicode = value,
+                ("Metadata", "Artist") => meta.artist = value,
+                ("Metadata", "ArtistUnicode") => meta.artist_unicode = value,
+                ("Metadata", "BeatmapID") => meta.bid = value.parse().unwrap_or_default(),
+                ("Metadata", "BeatmapSetID") => meta.sid = value.parse().unwrap_or_default(),
+                _ => {}
+            }
+        }
+
+        if meta.title.is_empty() {
+            return None;
+        }
+        // 没有 unicode 字段的旧谱面
+        if meta.title_unicode.is_empty() {
+            meta.title_unicode = meta.title.clone();
+        }
+        if meta.artist_unicode.is_empty() {
+            meta.artist_unicode = meta.artist.clone();
+        }
+        Some(meta)
+    }
+
+    /// `length` 为音频时长, 毫秒, 未知时为 -1
+    pub fn into_song(self, length: i32, audio_hash: Option<String>) -> OsuSongInfo {
+        OsuSongInfo {
+            bid: self.bid,
+            sid: self.sid,
+            length,
+            now: 0,
+            artist: self.artist,
+            artist_unicode: self.artist_unicode,
+            title: self.title,
+            title_unicode: self.title_unicode,
+            browsing: false,
+            audio_hash,
+            audio_path: None,
+        }
+    }
+}
+
+/// Songs 目录下的一个谱面集
+#[derive(Debug, Clone)]
+pub struct OsuSetEntry {
+    pub meta: OsuFileMeta,
+    pub audio_path: PathBuf,
+}
+
+/// 遍历 Songs 目录, 每个谱面集文件夹取第一个能读取的 `.osu` 文件
+///
+/// 同一个谱面集的歌词按 sid 共用, 不需要每个难度都处理
+pub fn scan_songs_folder(songs: &Path) -> std::io::Result<Vec<OsuSetEntry>> {
+    let mut folders = std::fs::read_dir(songs)?
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect::<Vec<_>>();
+    folders.sort();
+
+    let mut result = Vec::with_capacity(folders.len());
+    for folder in folders {
+        let Ok(entries) = std::fs::read_dir(&folder) else {
+            continue;
+        };
+        let mut files = entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "osu"))
+            .collect::<Vec<_>>();
+        files.sort();
+
+        let meta = files.iter().find_map(|file| {
+            let bytes = std::fs::read(file).ok()?;
+            OsuFileMeta::parse(&String::from_utf8_lossy(&bytes))
+        });
+        let Some(mut meta) = meta else {
+            continue;
+        };
+        // 旧谱面没有 BeatmapSetID, 文件夹名以 sid 开头
+        if meta.sid <= 0 {
+            meta.sid = folder_sid(&folder).unwrap_or_default();
+        }
+        let audio_path = folder.join(&meta.audio_filename);
+        result.push(OsuSetEntry { meta, audio_path });
+    }
+    Ok(result)
+}
+
+fn folder_sid(folder: &Path) -> Option<i64> {
+    let name = folder.file_name()?.to_str()?;
+    let digits = name.split(' ').next()?;
+    digits.parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OSU_FILE: &str = "osu file format v14
+
+[General]
+AudioFilename: audio.mp3
+AudioLeadIn: 0
+
+[Editor]
+DistanceSpacing: 1
+
+[Metadata]
+Title:Clair de lune
+TitleUnicode:月光
+Artist:Claude Debussy
+ArtistUnicode:Claude Debussy
+Creator:fixture
+Version:Normal
+BeatmapID:36001
+BeatmapSetID:36000
+
+[HitObjects]
+256,192,1000,1,0,0:0:0:0:
+";
+
+    #[test]
+    fn test_parse_osu_file() {
+        let meta = OsuFileMeta::parse(OSU_FILE).unwrap();
+        assert_eq!(meta.audio_filename, "audio.mp3");
+        assert_eq!(meta.title, "Clair de lune");
+        assert_eq!(meta.title_unicode, "月光");
+        assert_eq!(meta.artist_unicode, "Claude Debussy");
+        assert_eq!(meta.bid, 36001);
+        assert_eq!(meta.sid, 36000);
+    }
+
+    #[test]
+    fn test_parse_old_osu_file() {
+        let meta = OsuFileMeta::parse(
+            "osu file format v5\n\n[General]\nAudioFilename: song.mp3\n\n[Metadata]\nTitle:Old Song\nArtist:Someone\n",
+        )
+        .unwrap();
+        assert_eq!(meta.title_unicode, "Old Song");
+        assert_eq!(meta.artist_unicode, "Someone");
+        assert_eq!(meta.bid, 0);
+        assert!(OsuFileMeta::parse("[General]\nAudioFilename: a.mp3\n").is_none());
+    }
+}
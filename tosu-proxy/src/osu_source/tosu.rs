@@ -1,4 +1,4 @@
-use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
 
 use crate::error::Error;
 use crate::model::tosu_types::TosuApi;
@@ -25,6 +25,8 @@ pub struct TosuWebsocketClient {
     sid: AtomicI64,
     /// 当前音频文件
     audio_file: Mutex<String>,
+    /// 是否在选歌界面
+    browsing: AtomicBool,
     /// 重连延迟（毫秒）
     reconnect_delay_ms: u64,
     /// 连接超时（毫秒）
@@ -53,6 +55,7 @@ impl TosuWebsocketClient {
             bid: AtomicI64::default(),
             sid: AtomicI64::default(),
             audio_file: Mutex::new(String::new()),
+            browsing: AtomicBool::new(false),
             reconnect_delay_ms: 1000,
             connection_timeout_ms: 5000,
             heartbeat_timeout_ms: 3000,
@@ -71,6 +74,7 @@ impl TosuWebsocketClient {
             bid: AtomicI64::default(),
             sid: AtomicI64::default(),
             audio_file: Mutex::new(String::new()),
+            browsing: AtomicBool::new(false),
             reconnect_delay_ms,
             connection_timeout_ms,
             heartbeat_timeout_ms,
@@ -218,12 +222,16 @@ impl TosuWebsocketClient {
             // return;
         }
 
+        super::set_songs_folder(&tosu_data.folders.songs);
+
         let bid = tosu_data.beatmap.id;
         let sid = tosu_data.beatmap.set;
         let now = tosu_data.beatmap.time.live;
 
         let old_bid = self.bid.load(Ordering::SeqCst);
         let old_sid = self.sid.load(Ordering::SeqCst);
+        let browsing = tosu_data.is_song_select();
+        let was_browsing = self.browsing.swap(browsing, Ordering::SeqCst);
 
         // 检查是否需要更新歌曲信息
         let update_song_info = {
@@ -231,8 +239,11 @@ impl TosuWebsocketClient {
             old_sid != sid || (old_bid != bid && *current_audio != tosu_data.files.audio)
         };
 
-        if update_song_info {
-            self.update_song_info(tosu_data, bid, sid, now).await;
+        // 从选歌界面开始游玩时谱面不变, 也要通知一次, 以便预取没完成时正常搜索
+        let start_play = crate::service::is_start_play(was_browsing, tosu_data.is_playing());
+        if update_song_info || start_play {
+            self.update_song_info(tosu_data, bid, sid, now, browsing)
+                .await;
         } else {
             // 仅更新时间
             self.on_osu_state_change(super::OsuState::Time(now)).await;
@@ -240,7 +251,14 @@ impl TosuWebsocketClient {
     }
 
     /// 更新歌曲信息
-    async fn update_song_info(&self, mut tosu_data: TosuApi, bid: i64, sid: i64, now: i32) {
+    async fn update_song_info(
+        &self,
+        mut tosu_data: TosuApi,
+        bid: i64,
+        sid: i64,
+        now: i32,
+        browsing: bool,
+    ) {
         // 更新状态
         self.sid.store(sid, Ordering::SeqCst);
         self.bid.store(bid, Ordering::SeqCst);
@@ -269,6 +287,10 @@ impl TosuWebsocketClient {
             artist_unicode: tosu_data.beatmap.artist_unicode.take().unwrap_or_default(),
             title: tosu_data.beatmap.title.take().unwrap_or_default(),
             title_unicode: tosu_data.beatmap.title_unicode.take().unwrap_or_default(),
+            browsing,
+            // 选歌界面划过时不读整个文件, 防抖之后再计算
+            audio_hash: None,
+            audio_path: Some(tosu_data.audio_path()),
         };
 
         self.on_osu_state_change(super::OsuState::Song(info)).await;
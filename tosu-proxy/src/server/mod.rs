@@ -1,6 +1,8 @@
 mod audio;
 mod file;
 mod font;
+mod precache;
+mod source;
 mod websocket;
 
 use crate::config::GLOBAL_CONFIG;
@@ -8,6 +10,8 @@ use crate::error::*;
 use crate::server::audio::get_audio_route;
 use crate::server::file::get_file_route;
 use crate::server::font::get_font_route;
+use crate::server::precache::get_precache_route;
+use crate::server::source::get_source_route;
 use crate::server::websocket::get_ws_route;
 use salvo::prelude::Redirect;
 use salvo::server::ServerHandle;
@@ -27,7 +31,9 @@ pub async fn start_server() {
     use salvo::prelude::*;
     let api_router = Router::with_path("api")
         .push(get_font_route())
-        .push(get_audio_route());
+        .push(get_audio_route())
+        .push(get_source_route())
+        .push(get_precache_route());
     let router = Router::new()
         .get(root_redirect)
         .push(get_ws_route())
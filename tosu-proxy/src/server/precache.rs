@@ -0,0 +1,12 @@
+use crate::config::CONFIG_ENDPOINT_PRECACHE_STATUS;
+use crate::service::PRECACHE_SERVICE;
+use salvo::prelude::*;
+
+#[handler]
+async fn get_precache_status(res: &mut Response) {
+    res.render(Json(PRECACHE_SERVICE.status()));
+}
+
+pub fn get_precache_route() -> Router {
+    Router::with_path(CONFIG_ENDPOINT_PRECACHE_STATUS).get(get_precache_status)
+}
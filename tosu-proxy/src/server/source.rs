@@ -0,0 +1,12 @@
+use crate::config::CONFIG_ENDPOINT_SOURCE_STATUS;
+use crate::service::LyricService;
+use salvo::prelude::*;
+
+#[handler]
+async fn get_source_status(res: &mut Response) {
+    res.render(Json(LyricService::get_source_status()));
+}
+
+pub fn get_source_route() -> Router {
+    Router::with_path(CONFIG_ENDPOINT_SOURCE_STATUS).get(get_source_status)
+}
@@ -93,12 +93,26 @@ impl WebsocketSession {
         }
     }
 
+    /// 测试用, 和歌词页面一样接收推送
+    #[cfg(test)]
+    pub(crate) async fn add_test_client(&self) -> UnboundedReceiver<Message> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        self.add_client(ClientType::Client(tx)).await;
+        rx
+    }
+
     pub async fn send_to_all_client(&self, message: Message) {
         self.find_clients(message, |_, client| {
             matches!(*client, ClientType::Client(_))
         })
         .await;
     }
+    pub async fn send_to_all_setter(&self, message: Message) {
+        self.find_clients(message, |_, client| {
+            matches!(*client, ClientType::Setter(_))
+        })
+        .await;
+    }
     pub async fn send_to_one_client<T>(&self, key: &T, message: Message)
     where
         T: AsRef<str>,
@@ -0,0 +1,226 @@
+//! 按范围拉黑歌词
+//!
+//! 规则按 [`BlockScope`] 的顺序决定优先级: bid > sid > 标题 > 艺术家 > 正则,
+//! 匹配到的最具体的规则生效, 同一范围内拉黑优先于例外。
+//! `setBlock` 写入 `lyric_config` 的拉黑视为 bid 范围的规则, 只对这一张谱面生效
+
+use crate::database::{BlockRuleEntity, LyricConfigEntity};
+use crate::error::Result;
+use crate::model::websocket::setting::block::{BlockRule, BlockRuleRequest, BlockScope};
+use crate::osu_source::OsuSongInfo;
+use regex::{Regex, RegexBuilder};
+use std::collections::HashMap;
+use std::sync::{LazyLock, Mutex};
+use tracing::warn;
+
+/// 正则的长度上限, 避免构造过大的正则
+const MAX_PATTERN_LEN: usize = 256;
+
+pub async fn get_rules() -> Result<Vec<BlockRule>> {
+    Ok(BlockRuleEntity::find_all()
+        .await?
+        .into_iter()
+        .filter_map(|model| {
+            Some(BlockRule {
+                id: model.id,
+                scope: BlockScope::from_name(&model.scope)?,
+                value: model.value,
+                block: model.block,
+                created_at: model.created_at,
+            })
+        })
+        .collect())
+}
+
+pub async fn add_rule(request: BlockRuleRequest) -> Result<BlockRule> {
+    let value = request.value.trim();
+    if value.is_empty() {
+        return Err("value is empty".into());
+    }
+    match request.scope {
+        BlockScope::Bid | BlockScope::Sid => {
+            value
+                .parse::<i64>()
+                .map_err(|_| format!("{} 必须是数字", request.scope.as_str()))?;
+        }
+        BlockScope::Pattern => {
+            if value.len() > MAX_PATTERN_LEN {
+                return Err(format!("正则不能超过 {MAX_PATTERN_LEN} 个字符").into());
+            }
+            RegexBuilder::new(value)
+                .build()
+                .map_err(|err| format!("正则无效: {err}"))?;
+        }
+        BlockScope::Title | BlockScope::Artist => {}
+    }
+    let model = BlockRuleEntity::save(request.scope.as_str(), value, request.block).await?;
+    Ok(BlockRule {
+        id: model.id,
+        scope: request.scope,
+        value: model.value,
+        block: model.block,
+        created_at: model.created_at,
+    })
+}
+
+pub async fn delete_rule(id: i32) -> Result<()> {
+    if !BlockRuleEntity::delete_rule(id).await? {
+        return Err(format!("没有 id 为 {id} 的规则").into());
+    }
+    Ok(())
+}
+
+/// 删除这张谱面的 bid 例外, 用于 `setBlock`
+pub async fn remove_bid_exception(bid: i64) -> Result<()> {
+    BlockRuleEntity::delete_exception(BlockScope::Bid.as_str(), &bid.to_string()).await
+}
+
+/// `song` 是否被拉黑
+pub async fn is_blocked(song: &OsuSongInfo) -> Result<bool> {
+    let mut rules = get_rules().await?;
+    // setBlock 拉黑的只看 bid, 不再按标题和 sid 扩大范围
+    if let Some((true, _)) = LyricConfigEntity::get_by_bid(song.bid as i32).await? {
+        rules.push(BlockRule {
+            id: 0,
+            scope: BlockScope::Bid,
+            value: song.bid.to_string(),
+            block: true,
+            created_at: 0,
+        });
+    }
+    Ok(deciding_rule(&rules, song).is_some_and(|rule| rule.block))
+}
+
+/// 匹配 `song` 的规则中最具体的一条
+fn deciding_rule<'a>(rules: &'a [BlockRule], song: &OsuSongInfo) -> Option<&'a BlockRule> {
+    rules
+        .iter()
+        .filter(|rule| matches(rule, song))
+        .min_by_key(|rule| (rule.scope, !rule.block))
+}
+
+fn matches(rule: &BlockRule, song: &OsuSongInfo) -> bool {
+    let eq = |a: &str, b: &str| !a.is_empty() && a.trim().to_lowercase() == b.to_lowercase();
+    match rule.scope {
+        BlockScope::Bid => rule.value == song.bid.to_string(),
+        BlockScope::Sid => rule.value == song.sid.to_string(),
+        BlockScope::Title => eq(&song.title, &rule.value) || eq(&song.title_unicode, &rule.value),
+        BlockScope::Artist => {
+            eq(&song.artist, &rule.value) || eq(&song.artist_unicode, &rule.value)
+        }
+        BlockScope::Pattern => compiled_pattern(rule).is_some_and(|regex| {
+            regex.is_match(&song.title) || regex.is_match(&song.title_unicode)
+        }),
+    }
+}
+
+/// 编译过的正则, 每次切歌都会判断拉黑, 不重复编译。无效的正则也记下, 只警告一次
+fn compiled_pattern(rule: &BlockRule) -> Option<Regex> {
+    /// 超过后清空, 删掉的规则不会一直留着
+    const MAX_CACHED: usize = 256;
+    static CACHE: LazyLock<Mutex<HashMap<String, Option<Regex>>>> = LazyLock::new(Default::default);
+
+    let mut cache = CACHE.lock().unwrap();
+    if let Some(regex) = cache.get(&rule.value) {
+        return regex.clone();
+    }
+    let regex = RegexBuilder::new(&rule.value)
+        .build()
+        .inspect_err(|err| warn!("拉黑规则 {} 的正则无效: {}", rule.id, err))
+        .ok();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(rule.value.clone(), regex.clone());
+    regex
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_test_database;
+    use crate::lyric::fixture::test_song;
+
+    fn rule(id: i32, scope: BlockScope, value: &str, block: bool) -> BlockRule {
+        BlockRule {
+            id,
+            scope,
+            value: value.to_string(),
+            block,
+            created_at: 0,
+        }
+    }
+
+    #[test]
+    fn test_deciding_rule_precedence() {
+        let song = OsuSongInfo {
+            artist: "Claude Debussy".to_string(),
+            ..test_song(42001, 42000, "Clair de lune")
+        };
+        let rules = vec![
+            rule(1, BlockScope::Pattern, "(?i)^clair", true),
+            rule(2, BlockScope::Artist, "claude debussy", false),
+        ];
+        // 艺术家比正则具体
+        assert_eq!(deciding_rule(&rules, &song).map(|rule| rule.id), Some(2));
+
+        let mut rules = rules;
+        rules.push(rule(3, BlockScope::Title, "CLAIR DE LUNE", true));
+        rules.push(rule(4, BlockScope::Sid, "42000", false));
+        rules.push(rule(5, BlockScope::Sid, "42000", true));
+        // 同一范围拉黑优先
+        assert_eq!(deciding_rule(&rules, &song).map(|rule| rule.id), Some(5));
+        rules.push(rule(6, BlockScope::Bid, "42001", false));
+        assert_eq!(deciding_rule(&rules, &song).map(|rule| rule.id), Some(6));
+
+        let other = test_song(42101, 42100, "Arabesque");
+        let rules = vec![rule(1, BlockScope::Title, "Clair de lune", true)];
+        assert!(deciding_rule(&rules, &other).is_none());
+    }
+
+    #[test]
+    fn test_pattern_rule() {
+        let pattern = rule(1, BlockScope::Pattern, "^Arabesque No\\. \\d$", true);
+        // 第二次用缓存的正则
+        for _ in 0..2 {
+            let song = test_song(42301, 42300, "Arabesque No. 1");
+            assert!(matches(&pattern, &song));
+            assert!(!matches(&pattern, &test_song(42303, 42302, "Arabesque")));
+        }
+        assert!(compiled_pattern(&rule(2, BlockScope::Pattern, "(", true)).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_block_rule_crud() -> Result<()> {
+        init_test_database().await;
+        let invalid = BlockRuleRequest {
+            scope: BlockScope::Pattern,
+            value: "(".to_string(),
+            block: true,
+        };
+        assert!(add_rule(invalid).await.is_err());
+        let not_number = BlockRuleRequest {
+            scope: BlockScope::Sid,
+            value: "abc".to_string(),
+            block: true,
+        };
+        assert!(add_rule(not_number).await.is_err());
+
+        let song = test_song(42201, 42200, "Reverie");
+        assert!(!is_blocked(&song).await?);
+        let request = BlockRuleRequest {
+            scope: BlockScope::Sid,
+            value: "42200".to_string(),
+            block: true,
+        };
+        let added = add_rule(request.clone()).await?;
+        assert!(is_blocked(&song).await?);
+        // 同一条规则只保留一条
+        assert_eq!(add_rule(request).await?.id, added.id);
+
+        delete_rule(added.id).await?;
+        assert!(!is_blocked(&song).await?);
+        assert!(delete_rule(added.id).await.is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,35 @@
+//! 歌词服务测试的公共准备: 测试数据库, 本地歌词源, 使用它的服务
+
+use super::LyricService;
+use crate::database::init_test_database;
+use crate::lyric::LyricSourceEnum;
+use crate::lyric::fixture::FixtureServer;
+use tokio::sync::Mutex;
+
+pub(crate) struct TestService {
+    pub(crate) server: FixtureServer,
+    pub(crate) sources: Vec<LyricSourceEnum>,
+    /// 后台任务需要 `'static`, 测试里直接泄漏
+    pub(crate) service: &'static Mutex<LyricService>,
+}
+
+impl TestService {
+    /// 使用全部录制数据的 QQ 歌词源
+    pub(crate) async fn start() -> Self {
+        Self::with_server(FixtureServer::start_all().await, FixtureServer::qq_source).await
+    }
+
+    pub(crate) async fn with_server(
+        server: FixtureServer,
+        source: fn(&FixtureServer) -> LyricSourceEnum,
+    ) -> Self {
+        init_test_database().await;
+        let sources = vec![source(&server)];
+        let service = LyricService::with_sources(sources.clone());
+        Self {
+            server,
+            sources,
+            service: Box::leak(Box::new(Mutex::new(service))),
+        }
+    }
+}
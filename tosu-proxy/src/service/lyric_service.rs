@@ -1,22 +1,67 @@
-use crate::database::{LyricCacheEntity, LyricConfigEntity};
+use super::{block_service, search_override_service};
+use crate::database::{
+    BeatmapLyricEntity, BeatmapLyricModel, LyricCacheEntity, LyricCacheModel, LyricConfigEntity,
+    LyricMissEntity, SettingEntity, SharedLyricEntity, SharedLyricModel,
+};
 use crate::error::{Error, Result};
 use crate::lyric::{
-    Lyric, LyricLine, LyricResult, LyricSource, LyricSourceEnum, NETEASE_LYRIC_SOURCE,
-    QQ_LYRIC_SOURCE, SongInfo, SongInfoKey,
+    CircuitState, Lyric, LyricLine, LyricOrigin, LyricResult, LyricSource, LyricSourceEnum,
+    MAX_SEARCH_LIMIT, MAX_SEARCH_PAGE, SongInfo, SongInfoKey, SourceStatus, TapRecorder,
+    background,
 };
 use crate::model::websocket::WebSocketMessage;
 use crate::model::websocket::lyric::{LyricLinePayload, LyricPayload, SequenceType};
-use crate::model::websocket::setting::block::BlockItem;
+use crate::model::websocket::setting::assign::AssignLyricRequest;
+use crate::model::websocket::setting::block::{BlockItem, BlockRuleRequest, BlockScope};
+use crate::model::websocket::setting::cache::{CacheKey, CacheListResult, CachePin, CacheQuery};
+use crate::model::websocket::setting::lyric_info::LyricInfo;
+use crate::model::websocket::setting::lyric_sync::{LyricSync, SyncAnchor};
+use crate::model::websocket::setting::miss::MissItem;
+use crate::model::websocket::setting::offset::{
+    LyricOffset, OffsetItem, OffsetListResult, OffsetQuery, OffsetSetting, SourceOffsetRequest,
+};
+use crate::model::websocket::setting::search::{SearchLyricRequest, SearchLyricResult};
+use crate::model::websocket::setting::tap_sync::{TapSyncRequest, TapSyncStatus};
 use crate::osu_source::OsuSongInfo;
 use crate::server::ALL_SESSIONS;
 use sea_orm::EntityTrait;
+use sea_orm::sqlx::types::chrono::Utc;
 use serde_json::Value;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::ops::Deref;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, LazyLock};
-use tokio::sync::{broadcast, Mutex};
+use tokio::sync::{Mutex, broadcast};
 use tokio::task::JoinSet;
-use tracing::{debug, error};
+use tracing::{debug, error, warn};
+
+/// 合并翻译时对时长的要求比自动选歌词更严格, 毫秒
+const MERGE_ALLOW_OFFSET: u32 = 3000;
+/// 合并翻译时每个源最多尝试的候选数
+const MERGE_CANDIDATES: usize = 2;
+const MISS_RETRY_SETTING_KEY: &str = "missRetry";
+/// 没有歌词的谱面默认隔多久重新搜索, 小时
+const DEFAULT_MISS_RETRY_HOURS: u64 = 72;
+/// 重新搜索间隔的上限, 5 年
+const MAX_MISS_RETRY_HOURS: u64 = 24 * 365 * 5;
+/// 列表每页的最大数量
+const MAX_LIST_PAGE_SIZE: u32 = 100;
+
+const CACHE_TTL_SETTING_KEY: &str = "cacheTtl";
+/// 缓存有效期的上限, 5 年
+const MAX_CACHE_TTL_HOURS: u64 = 24 * 365 * 5;
+const OFFSET_SETTING_KEY: &str = "offsetSetting";
+/// 两个同步点至少相隔的播放时间, 太近时算出的倍率误差很大, 毫秒
+const MIN_ANCHOR_DISTANCE: i32 = 10_000;
+/// 允许的倍率范围
+const MIN_SCALE: f64 = 0.9;
+const MAX_SCALE: f64 = 1.1;
+
+static MISS_RETRY_HOURS: AtomicU64 = AtomicU64::new(DEFAULT_MISS_RETRY_HOURS);
+/// 自动获取的歌词缓存多久后重新搜索, 小时, 0 表示不过期
+static CACHE_TTL_HOURS: AtomicU64 = AtomicU64::new(0);
+/// 正在后台刷新的谱面, 避免重复刷新
+static REFRESHING: LazyLock<std::sync::Mutex<HashSet<i32>>> = LazyLock::new(Default::default);
 
 pub static LYRIC_SERVICE: LazyLock<Mutex<LyricService>> =
     LazyLock::new(|| Mutex::new(LyricService::default()));
@@ -25,15 +70,26 @@ pub struct LyricService {
     // 当前歌词的下标
     now_index: usize,
     now_lyric: Option<Lyric>,
+    // 当前歌词的来源, 存缓存时一起记录
+    now_lyric_origin: Option<LyricOrigin>,
     now_save_cache: Option<OsuSongInfo>,
     // 偏移值, 毫秒
     offset: i32,
+    // 播放时间的倍率, 和 offset 一起换算成歌词时间
+    scale: f64,
+    // 全局和按歌词源的偏移, 和 offset 相加
+    offset_setting: OffsetSetting,
+    // 最近一次收到的播放时间, 毫秒, 不含偏移
+    play_time: i32,
 
     // 当前歌词的起始/终止时间 毫秒
     current_lyric_start_time: i32,
     current_lyric_end_time: i32,
 
     music_cache: Arc<Mutex<HashMap<&'static str, Vec<SongInfo>>>>,
+    sources: Vec<LyricSourceEnum>,
+    // 每次换歌加一, 用于丢弃过期的搜索结果
+    generation: u64,
 
     // 任务取消通道
     cancel_tx: broadcast::Sender<()>,
@@ -43,67 +99,351 @@ pub struct LyricService {
 
     // 当前歌曲是否切换的状态
     is_song_changed: bool,
+
+    // 正在打轴的谱面和记录
+    tap_sync: Option<(OsuSongInfo, TapRecorder)>,
 }
 
 impl LyricService {
     pub fn new() -> Self {
-        let mut cache = HashMap::with_capacity(2);
-        cache.insert(QQ_LYRIC_SOURCE.name(), Vec::with_capacity(10));
-        cache.insert(NETEASE_LYRIC_SOURCE.name(), Vec::with_capacity(10));
+        Self::with_sources(LyricSourceEnum::all())
+    }
+
+    /// `sources` 的顺序即搜索结果相同时的优先顺序
+    pub fn with_sources(sources: Vec<LyricSourceEnum>) -> Self {
+        let cache = sources
+            .iter()
+            .map(|source| (source.name(), Vec::with_capacity(10)))
+            .collect();
         // 创建任务取消通道
         let (cancel_tx, cancel_rx) = broadcast::channel(1);
         Self {
             now_index: 0,
             now_lyric: None,
+            now_lyric_origin: None,
             now_save_cache: None,
             offset: 0,
+            scale: 1.0,
+            offset_setting: OffsetSetting::default(),
+            play_time: 0,
             current_lyric_start_time: -1,
             current_lyric_end_time: -1,
             // 使用 Arc 和 Mutex 包装缓存
             music_cache: Arc::new(Mutex::new(cache)),
+            sources,
+            generation: 0,
             cancel_tx,
             cancel_rx: Mutex::new(Some(cancel_rx)),
             wait_tasks: Mutex::new(None),
             is_song_changed: true,
+            tap_sync: None,
+        }
+    }
+
+    /// 换歌, 网络搜索期间不持有 `service` 的锁, 时间更新和设置请求可以照常处理
+    ///
+    /// 搜索完成时如果已经换了别的歌, 结果直接丢弃
+    pub async fn song_change(service: &Mutex<Self>, song: OsuSongInfo) -> Result<()> {
+        let search = {
+            let mut this = service.lock().await;
+            match this.prepare_song_change(song).await? {
+                Some(search) => search,
+                None => return Ok(()),
+            }
+        };
+
+        let (outcome, join_set) = search.run().await;
+
+        let mut this = service.lock().await;
+        if this.generation != search.generation {
+            debug!("搜索期间已切换歌曲, 丢弃 {} 的结果", search.title);
+            return Ok(());
+        }
+        // 其他源的搜索任务在后台继续, 结果用于 getLyricList
+        *this.wait_tasks.lock().await = Some(join_set);
+
+        let (lyric, origin) = match outcome {
+            SearchOutcome::Found(lyric, origin) => (lyric, origin),
+            SearchOutcome::NotFound | SearchOutcome::Instrumental => {
+                if let Some(song) = &this.now_save_cache {
+                    let instrumental = matches!(outcome, SearchOutcome::Instrumental);
+                    Self::save_miss(song, instrumental)
+                        .await
+                        .unwrap_or_else(|err| error!("记录无歌词异常: {}", err));
+                }
+                return Ok(());
+            }
+            SearchOutcome::Failed => return Ok(()),
+        };
+        let title = &search.title;
+        debug!("通过网络加载 {title}");
+        if let Some(save_key) = &this.now_save_cache {
+            match Self::save_lyric(save_key, &lyric, Some(&origin), false).await {
+                Ok(_) | Err(Error::LyricParse(_)) => {
+                    debug!("记录到缓存 {title}");
+                }
+                Err(err) => {
+                    error!("存储缓存异常: {}", err);
+                }
+            };
+        }
+        this.now_lyric = Some(lyric);
+        this.now_lyric_origin = Some(origin);
+        Ok(())
+    }
+
+    /// 只查本地缓存, 用于选歌界面; 返回 `false` 表示需要网络搜索
+    pub async fn song_change_cached(service: &Mutex<Self>, song: OsuSongInfo) -> Result<bool> {
+        let search = service.lock().await.prepare_song_change(song).await?;
+        Ok(search.is_none())
+    }
+
+    /// 不修改当前状态, 搜索 `song` 的歌词, 用于预取
+    pub(super) async fn resolve_lyric(
+        sources: &[LyricSourceEnum],
+        song: &OsuSongInfo,
+    ) -> SearchOutcome {
+        let cache = sources
+            .iter()
+            .map(|source| (source.name(), Vec::new()))
+            .collect();
+        // 保持发送端存活, 否则任务会当作已取消
+        let (_cancel_tx, cancel_rx) = broadcast::channel(1);
+        let (title, artist) = search_override_service::search_terms(song).await;
+        let search = SongSearch {
+            generation: 0,
+            title,
+            artist,
+            length: song_length(song),
+            sources: sources.to_vec(),
+            music_cache: Arc::new(Mutex::new(cache)),
+            cancel_rx,
+            failed: Arc::default(),
+            instrumental: AtomicBool::new(false),
+        };
+        // 返回时 JoinSet 被 drop, 剩下的搜索任务一并取消
+        let (outcome, _) = search.run().await;
+        outcome
+    }
+
+    /// 是否有歌词源处于熔断中, 后台任务据此让出请求
+    pub(super) fn any_circuit_open(sources: &[LyricSourceEnum]) -> bool {
+        sources
+            .iter()
+            .any(|source| source.guard().status().state == CircuitState::Open)
+    }
+
+    /// 搜索 `song` 的歌词并存入缓存, 已拉黑或已有缓存时跳过
+    pub(super) async fn cache_song(
+        sources: &[LyricSourceEnum],
+        song: &OsuSongInfo,
+    ) -> Result<CacheOutcome> {
+        let (bid, sid) = (song.bid as i32, song.sid as i32);
+        let title = song.title_unicode.as_str();
+        if block_service::is_blocked(song).await? {
+            return Ok(CacheOutcome::Blocked);
+        }
+        if Self::find_cached(song).await?.is_some() {
+            return Ok(CacheOutcome::Cached);
+        }
+        if Self::find_miss(bid, sid).await? {
+            return Ok(CacheOutcome::Missed);
+        }
+
+        match Self::resolve_lyric(sources, song).await {
+            SearchOutcome::Found(lyric, origin) => {
+                Self::save_lyric(song, &lyric, Some(&origin), false).await?;
+                Ok(CacheOutcome::Saved(lyric, origin))
+            }
+            SearchOutcome::NotFound => {
+                Self::save_miss(song, false).await?;
+                Ok(CacheOutcome::NotFound)
+            }
+            SearchOutcome::Instrumental => {
+                Self::save_miss(song, true).await?;
+                Ok(CacheOutcome::NotFound)
+            }
+            SearchOutcome::Failed => Ok(CacheOutcome::Failed),
+        }
+    }
+
+    /// 谱面自己的缓存先按 bid 再按 sid 查找, 之后按音频哈希查找共用的歌词
+    ///
+    /// 相同音频手动选择过的歌词优先, 除非这张谱面自己也手动选择过。
+    /// 删除过缓存的谱面不再使用删除时的共享歌词
+    async fn find_cached(song: &OsuSongInfo) -> Result<Option<CachedLyric>> {
+        let bid = song.bid as i32;
+        let cache = match LyricCacheEntity::find_by_bid(bid).await? {
+            Some(cache) => Some(cache),
+            None => LyricCacheEntity::find_by_sid(song.sid as i32).await?,
+        };
+        let mut shared = match &song.audio_hash {
+            Some(audio_hash) => SharedLyricEntity::find_by_hash(audio_hash).await?,
+            None => None,
+        };
+        if let Some(model) = &shared
+            && BeatmapLyricEntity::is_detached(bid, model.id).await?
+        {
+            shared = None;
+        }
+        Ok(match (cache, shared) {
+            (Some(cache), _) if cache.manual => Some(CachedLyric::Beatmap(cache)),
+            (_, Some(shared)) if shared.manual => Some(CachedLyric::Shared(shared)),
+            (Some(cache), _) => Some(CachedLyric::Beatmap(cache)),
+            (None, shared) => shared.map(CachedLyric::Shared),
+        })
+    }
+
+    /// 在后台重新搜索, 本次游玩继续使用缓存
+    fn spawn_refresh(&self, song: OsuSongInfo, cached: CachedLyric, score: (usize, usize)) {
+        let bid = song.bid as i32;
+        if !REFRESHING.lock().unwrap().insert(bid) {
+            return;
+        }
+        let sources = self.sources.clone();
+        tokio::spawn(async move {
+            if let Err(err) = Self::refresh_cache(&sources, &song, &cached, score).await {
+                warn!("刷新缓存 {} 失败: {}", song.title_unicode, err);
+            }
+            REFRESHING.lock().unwrap().remove(&bid);
+        });
+    }
+
+    /// 新歌词比缓存的 `score` 更完整时才替换, 否则只更新 `cached` 的获取时间
+    ///
+    /// `cached` 可能是同一谱面集其他难度的缓存或相同音频的歌词, 见 [`Self::find_cached`]
+    async fn refresh_cache(
+        sources: &[LyricSourceEnum],
+        song: &OsuSongInfo,
+        cached: &CachedLyric,
+        score: (usize, usize),
+    ) -> Result<()> {
+        let title = song.title_unicode.as_str();
+        if Self::any_circuit_open(sources) {
+            debug!("歌词源熔断中, 暂不刷新 {title}");
+            return Ok(());
+        }
+        debug!("缓存已过期, 重新搜索 {title}");
+        let lyric = match Self::resolve_lyric(sources, song).await {
+            SearchOutcome::Found(lyric, origin) => Some((lyric, origin)),
+            SearchOutcome::NotFound | SearchOutcome::Instrumental => None,
+            // 下次游玩再试
+            SearchOutcome::Failed => return Ok(()),
+        };
+
+        // 搜索期间可能手动选择或固定了歌词
+        let current = match cached {
+            CachedLyric::Beatmap(cache) => LyricCacheEntity::find_by_bid(cache.bid)
+                .await?
+                .map(CachedLyric::Beatmap),
+            CachedLyric::Shared(shared) => SharedLyricEntity::find_by_hash(&shared.audio_hash)
+                .await?
+                .map(CachedLyric::Shared),
+        };
+        if current.is_none_or(|current| current.is_kept()) {
+            return Ok(());
+        }
+        match lyric {
+            Some((lyric, origin)) if lyric.score() > score => {
+                debug!("使用刷新后的歌词 {title}");
+                Self::save_lyric(song, &lyric, Some(&origin), false).await
+            }
+            _ => {
+                let now = Utc::now().timestamp();
+                match cached {
+                    CachedLyric::Beatmap(cache) => {
+                        LyricCacheEntity::set_fetched_at(cache.bid, now).await
+                    }
+                    CachedLyric::Shared(shared) => {
+                        SharedLyricEntity::set_fetched_at(shared.id, now).await
+                    }
+                }
+            }
         }
     }
 
-    pub async fn song_change(&mut self, song: OsuSongInfo) -> Result<()> {
+    /// 预取完成时, 如果还停留在这张谱面且没有歌词, 直接使用
+    pub(super) async fn apply_prefetched(
+        service: &Mutex<Self>,
+        song: &OsuSongInfo,
+        lyric: Lyric,
+        origin: LyricOrigin,
+    ) {
+        let mut this = service.lock().await;
+        let is_current = this
+            .now_save_cache
+            .as_ref()
+            .is_some_and(|now| now.bid == song.bid);
+        if is_current && this.now_lyric.is_none() {
+            debug!("使用预取的歌词 {}", song.title_unicode);
+            this.now_lyric = Some(lyric);
+            this.now_lyric_origin = Some(origin);
+        }
+    }
+
+    /// 重置状态并查询本地缓存, 需要网络搜索时返回搜索任务
+    async fn prepare_song_change(&mut self, song: OsuSongInfo) -> Result<Option<SongSearch>> {
+        // 从选歌界面开始游玩时会再通知一次同一张谱面, 已经有歌词就不用重新加载
+        let is_current = self
+            .now_save_cache
+            .as_ref()
+            .is_some_and(|now| now.bid == song.bid);
+        if is_current && self.now_lyric.is_some() {
+            return Ok(None);
+        }
+
         self.clear_cache().await;
+        self.generation += 1;
+        // 搜索期间不能继续显示上一首的歌词
+        self.now_lyric = None;
+        self.current_lyric_start_time = -1;
+        self.current_lyric_end_time = -1;
 
         let title = song.title_unicode.to_string();
-        let artist = song.artist_unicode.to_string();
 
         let bid = song.bid as i32;
         let sid = song.sid as i32;
-        let length = if song.length < 0 {
-            0u32
-        } else {
-            song.length as u32
-        };
+        let length = song_length(&song);
 
+        let blocked = block_service::is_blocked(&song).await?;
         self.now_save_cache = Some(song);
 
-        let (disable, offset) = LyricConfigEntity::find_setting(bid, sid, &title).await?;
-
-        if disable {
-            return Ok(());
+        if blocked {
+            return Ok(None);
         } else {
-            self.offset = offset;
+            (self.offset, self.scale) = LyricConfigEntity::find_timing(bid, sid, &title).await?;
         }
 
         // 先查询缓存
-        let cache = match LyricCacheEntity::find_by_bid(bid).await? {
-            Some(v) => Some(v),
-            None => LyricCacheEntity::find_by_sid(sid).await?,
+        let cached = match &self.now_save_cache {
+            Some(song) => Self::find_cached(song).await?,
+            None => None,
         };
 
-        if let Some(cache) = cache {
-            match Lyric::from_json_cache(cache.cache.as_slice()) {
+        if let Some(cached) = cached {
+            match cached.lyric() {
                 Ok(lyric) => {
+                    if let CachedLyric::Shared(shared) = &cached
+                        && let Some(song) = &self.now_save_cache
+                    {
+                        debug!("通过相同音频的歌词加载 {title}");
+                        BeatmapLyricEntity::link(song, shared.id).await?;
+                    } else {
+                        debug!("通过缓存加载 {title}");
+                    }
+                    self.now_lyric_origin = cached.origin();
+                    // 选歌界面只是划过, 真正游玩时才刷新
+                    let song = self.now_save_cache.as_ref().filter(|song| !song.browsing);
+                    if let Some(song) = song
+                        && cached.is_expired(Self::cache_ttl())
+                    {
+                        self.spawn_refresh(song.clone(), cached, lyric.score());
+                    }
                     self.now_lyric = Some(lyric);
-                    debug!("通过缓存加载 {title}");
-                    return Ok(());
+                    return Ok(None);
+                }
+                Err(err) if matches!(cached, CachedLyric::Shared(_)) => {
+                    error!("共享歌词无效: {}", err);
                 }
                 Err(err) => {
                     LyricCacheEntity::delete_by_id(bid);
@@ -112,8 +452,10 @@ impl LyricService {
             }
         }
 
-        // 后台任务: 同时查询 网易云 | QQ, 用于当其中一个查到结果, 另一个还在进行时允许另一个在后台继续执行
-        let mut join_set = JoinSet::new();
+        if Self::find_miss(bid, sid).await? {
+            debug!("{title} 之前没有找到歌词, 跳过搜索");
+            return Ok(None);
+        }
 
         // 克隆取消通道接收器，为每个任务创建独立的接收器
         let cancel_rx = {
@@ -124,86 +466,137 @@ impl LyricService {
                 .resubscribe()
         };
 
-        Self::spawn_search_task(
-            &mut join_set,
-            &*QQ_LYRIC_SOURCE,
-            &title,
-            &artist,
-            Arc::clone(&self.music_cache),
-            cancel_rx.resubscribe(),
-        );
+        let (title, artist) = match &self.now_save_cache {
+            Some(song) => search_override_service::search_terms(song).await,
+            None => (title, String::new()),
+        };
+        Ok(Some(SongSearch {
+            generation: self.generation,
+            title,
+            artist,
+            length,
+            sources: self.sources.clone(),
+            music_cache: Arc::clone(&self.music_cache),
+            cancel_rx,
+            failed: Arc::default(),
+            instrumental: AtomicBool::new(false),
+        }))
+    }
 
-        Self::spawn_search_task(
-            &mut join_set,
-            &*NETEASE_LYRIC_SOURCE,
-            &title,
-            &artist,
-            Arc::clone(&self.music_cache),
-            cancel_rx.resubscribe(),
-        );
+    /// 编辑当前歌词, 存为手动选择的缓存(自动固定)并重新推送给歌词页面
+    pub async fn edit_lyric(
+        &mut self,
+        edit: impl FnOnce(&mut Lyric) -> Result<()>,
+    ) -> Result<&[LyricLine]> {
+        let lyric = self.now_lyric.as_mut().ok_or("no lyrics found")?;
+        edit(lyric)?;
+        if let Some(song) = &self.now_save_cache {
+            Self::save_lyric(song, lyric, self.now_lyric_origin.as_ref(), true)
+                .await
+                .inspect_err(|err| error!("存储缓存异常: {}", err))?;
+        }
+        self.push_all_lyric().await?;
+        Ok(self.get_now_all_lyrics().unwrap_or_default())
+    }
 
-        macro_rules! search_and_set {
-            (>$t:ident) => {
-                let search_success = self.search_and_set_lyric(&*$t, &title, length, &artist).await?;
-                if search_success
-                {
-                    debug!("通过网络加载 {title}");
-                    let Some(lyric) = &self.now_lyric else { return Ok(()); };
-                    let Some(save_key) = &self.now_save_cache else { return Ok(()); };
-                    match Self::save_lyric(save_key, lyric).await {
-                        Ok(_) | Err(Error::LyricParse(_)) => {
-                            debug!("记录到缓存 {title}");
-                        }
-                        Err(err) => {
-                            error!("存储缓存异常: {}", err);
-                        }
-                    };
-                    return Ok(());
-                }
-            };
-            (:$t:ident) => {
-                if self
-                    .search_and_set_lyric(&*$t, &title, length, &artist)
-                    .await?
-                {
-                    let mut tasks = self.wait_tasks.lock().await;
-                    *tasks = Some(join_set);
-                    drop(tasks);
-                    break;
-                }
-            };
-            ($name:ident:$t:ident) => {
-                if $name == $t.name() {
-                    search_and_set!(:$t)
-                }
-            }
+    /// 开始为当前歌曲打轴, 之前没保存的记录丢弃
+    pub fn start_tap_sync(&mut self, request: &TapSyncRequest) -> Result<TapSyncStatus> {
+        let song = self.now_save_cache.clone().ok_or("no save cache is set")?;
+        let recorder = TapRecorder::new(&request.text, request.trans_text.as_deref())?;
+        self.tap_sync = Some((song, recorder));
+        self.get_tap_sync().ok_or(Error::Impossible)
+    }
+
+    /// 按最近的播放时间记下下一行的开始时间
+    ///
+    /// 和 `time_next` 一样换算成歌词时间, 保存后按同样的偏移播放时正好在点击时显示;
+    /// 保存的歌词没有来源, 所以不算歌词源的偏移
+    pub fn tap_sync(&mut self) -> Result<TapSyncStatus> {
+        let time = (self.lyric_time(self.play_time) - self.source_offset()) as f32 / 1000f32;
+        let (song, _) = self.tap_sync.as_ref().ok_or("没有在打轴")?;
+        if !self.is_current(song.bid as u32) {
+            return Err("歌曲已切换".into());
         }
+        let (_, recorder) = self.tap_sync.as_mut().ok_or(Error::Impossible)?;
+        recorder.tap(time)?;
+        self.get_tap_sync().ok_or(Error::Impossible)
+    }
 
-        while let Some(source_name) = join_set.join_next().await {
-            let Ok(source_name) = source_name else {
-                continue;
-            };
-            search_and_set!(source_name:NETEASE_LYRIC_SOURCE);
-            search_and_set!(source_name:QQ_LYRIC_SOURCE);
+    pub fn undo_tap_sync(&mut self) -> Result<TapSyncStatus> {
+        let (_, recorder) = self.tap_sync.as_mut().ok_or("没有在打轴")?;
+        recorder.undo().ok_or("还没有打轴")?;
+        self.get_tap_sync().ok_or(Error::Impossible)
+    }
+
+    pub fn get_tap_sync(&self) -> Option<TapSyncStatus> {
+        let (song, recorder) = self.tap_sync.as_ref()?;
+        Some(TapSyncStatus {
+            bid: song.bid as u32,
+            total: recorder.total(),
+            lines: recorder.tapped_lines(),
+            next: recorder.next_line().map(str::to_string),
+        })
+    }
+
+    pub fn cancel_tap_sync(&mut self) {
+        self.tap_sync = None;
+    }
+
+    /// 用已打轴的行生成歌词, 存为手动选择的缓存, 还在这首歌时替换当前歌词
+    pub async fn save_tap_sync(&mut self) -> Result<Vec<LyricLine>> {
+        let (song, recorder) = self.tap_sync.as_ref().ok_or("没有在打轴")?;
+        let lyric = recorder.to_lyric()?;
+        Self::save_lyric(song, &lyric, None, true).await?;
+        let lines = lyric.get_lyrics().to_vec();
+        let bid = song.bid as u32;
+        self.tap_sync = None;
+        if self.is_current(bid) {
+            self.now_lyric = Some(lyric);
+            self.now_lyric_origin = None;
+            self.push_all_lyric().await?;
         }
+        Ok(lines)
+    }
 
-        search_and_set!(>NETEASE_LYRIC_SOURCE);
-        search_and_set!(>QQ_LYRIC_SOURCE);
+    /// 歌词内容变化后, 按最近的播放时间重新推送完整歌词和当前行
+    async fn push_all_lyric(&mut self) -> Result<()> {
+        self.is_song_changed = true;
+        self.now_index = usize::MAX;
+        self.current_lyric_start_time = -1;
+        self.current_lyric_end_time = -1;
+        self.time_next(self.play_time).await
+    }
 
-        self.now_lyric = None;
+    /// 播放时间换算成歌词时间, 毫秒
+    fn lyric_time(&self, play_time: i32) -> i32 {
+        (f64::from(play_time.max(0)) * self.scale).round() as i32 + self.total_offset()
+    }
 
-        Ok(())
+    /// 全局、歌词源和谱面的偏移之和
+    fn total_offset(&self) -> i32 {
+        self.offset_setting.global + self.source_offset() + self.offset
+    }
+
+    fn source(&self) -> Option<&str> {
+        let origin = self.now_lyric_origin.as_ref()?;
+        Some(&origin.lyric.source_type)
+    }
+
+    fn source_offset(&self) -> i32 {
+        self.source()
+            .and_then(|source| self.offset_setting.sources.get(source))
+            .copied()
+            .unwrap_or(0)
     }
 
     /// 时间单位为毫秒
     pub async fn time_next(&mut self, t: i32) -> Result<()> {
+        self.play_time = t;
+        let t = self.lyric_time(t);
         let Some(lyric) = &mut self.now_lyric else {
             return Ok(());
         };
 
-        let mut t = if t < 0 { 0 } else { t };
-        t += self.offset;
-
         if t >= self.current_lyric_start_time && t <= self.current_lyric_end_time {
             // 时间没变
             return Ok(());
@@ -270,6 +663,7 @@ impl LyricService {
     // 清理缓存
     async fn clear_cache(&mut self) {
         self.is_song_changed = true;
+        self.now_lyric_origin = None;
 
         // Step 1: 广播取消信号给所有活动任务
         let _ = self.cancel_tx.send(());
@@ -292,87 +686,65 @@ impl LyricService {
 
         // Step 5: 清空音乐缓存
         let mut cache = self.music_cache.lock().await;
-        if let Some(qq_cache) = cache.get_mut(QQ_LYRIC_SOURCE.name()) {
-            qq_cache.clear();
-        }
-        if let Some(netease_cache) = cache.get_mut(NETEASE_LYRIC_SOURCE.name()) {
-            netease_cache.clear();
-        }
+        cache.values_mut().for_each(Vec::clear);
     }
 
-    // 获取歌词存到 self.music_cache
-    async fn search_and_set_lyric<S: LyricSource + 'static>(
-        &mut self,
-        source: &'static S,
-        title: &str,
-        length: u32,
-        artist: &str,
-    ) -> Result<bool> {
-        let cache = self.music_cache.lock().await;
-        let cache_vec = cache.get(source.name());
-
-        let songs_to_search = cache_vec.unwrap();
+    pub fn music_cache(&self) -> Arc<Mutex<HashMap<&'static str, Vec<SongInfo>>>> {
+        Arc::clone(&self.music_cache)
+    }
 
-        if songs_to_search.is_empty() {
-            return Ok(false);
-        }
+    pub fn sources(&self) -> Vec<LyricSourceEnum> {
+        self.sources.clone()
+    }
 
-        let lyric_result = source
-            .search_lyrics(songs_to_search, title, length, artist)
-            .await?;
+    /// 不需要持有 `LYRIC_SERVICE` 的锁, 先用 [`Self::music_cache`] 取出缓存
+    ///
+    /// 还不知道的歌词标记在后台补上, 之后的请求才能看到
+    pub async fn get_search_result(
+        cache: Arc<Mutex<HashMap<&'static str, Vec<SongInfo>>>>,
+        sources: Vec<LyricSourceEnum>,
+    ) -> Value {
+        use serde_json::map::Map;
+        tokio::spawn(Self::fill_lyric_flags(Arc::clone(&cache), sources));
 
-        if let Some(lyric) = lyric_result {
-            self.now_lyric = Some(lyric.try_into()?);
-            return Ok(true);
+        let mut result: Map<String, Value> = Map::new();
+        let cache_map = cache.lock().await;
+        for key in cache_map.keys() {
+            Self::search_result_to_json(&cache_map, key.to_string(), &mut result).await;
         }
-
-        Ok(false)
+        Value::Object(result)
     }
 
-    fn spawn_search_task<S: LyricSource + 'static>(
-        tasks: &mut JoinSet<&'static str>,
-        source: &'static S,
-        title: &str,
-        artist: &str,
-        music_cache: Arc<Mutex<HashMap<&'static str, Vec<SongInfo>>>>,
-        mut cancel_rx: broadcast::Receiver<()>,
+    /// 搜索接口不提供的歌词标记(翻译/罗马音/逐字), 每首歌只获取一次歌词补上
+    ///
+    /// 作为后台请求发出, 失败不会导致歌词源熔断
+    async fn fill_lyric_flags(
+        cache: Arc<Mutex<HashMap<&'static str, Vec<SongInfo>>>>,
+        sources: Vec<LyricSourceEnum>,
     ) {
-        let title = title.to_string();
-        let artist = artist.to_string();
-        tasks.spawn(async move {
-            // 使用 tokio::select! 同时监听取消信号和搜索结果
-            let search_result = tokio::select! {
-                // 取消分支 - 收到信号立即退出
-                _ = cancel_rx.recv() => {
-                    return source.name();
-                }
-                // 搜索分支 - 执行实际的搜索操作
-                result = source.search_all_music(&title, &artist) => result,
-            };
+        // 先标记为已尝试, 同时到来的请求不会重复获取; 请求期间不持有缓存的锁
+        let untried = {
+            let mut cache_map = cache.lock().await;
+            cache_map
+                .iter_mut()
+                .flat_map(|(source, songs)| songs.iter_mut().map(move |song| (*source, song)))
+                .filter(|(_, song)| !song.lyric_flags_tried && !song.is_lyric_flags_known())
+                .map(|(source, song)| {
+                    song.lyric_flags_tried = true;
+                    (source, song.key.clone())
+                })
+                .collect::<Vec<_>>()
+        };
 
-            // 如果搜索成功（未被取消），处理结果
-            if let Ok(musics) = search_result {
-                let mut cache = music_cache.lock().await;
-                if let Some(cache_vec) = cache.get_mut(source.name()) {
-                    cache_vec.extend(musics);
-                }
+        for (name, key) in untried {
+            let Some(source) = LyricSourceEnum::find(&sources, name) else {
+                continue;
+            };
+            match background(source.fetch_lyrics(&key)).await {
+                Ok(lyric) => record_lyric_flags(&cache, name, &key, &lyric).await,
+                Err(err) => debug!("{} 获取歌词标记失败: {}", name, err),
             }
-
-            source.name()
-        });
-    }
-
-    pub async fn get_search_result(&self) -> Value {
-        use serde_json::map::Map;
-        let mut result: Map<String, Value> = Map::new();
-        let cache = Arc::clone(&self.music_cache);
-        let cache_map = cache.lock().await;
-
-        let qq_key = QQ_LYRIC_SOURCE.name().to_string();
-        let netease_key = NETEASE_LYRIC_SOURCE.name().to_string();
-        Self::search_result_to_json(&cache_map, qq_key, &mut result).await;
-        Self::search_result_to_json(&cache_map, netease_key, &mut result).await;
-        Value::Object(result)
+        }
     }
 
     async fn search_result_to_json(
@@ -393,68 +765,279 @@ impl LyricService {
         result.insert(key, Value::Array(data_vec));
     }
 
-    pub async fn set_song_by_key(&mut self, key_info: &SongInfoKey) -> Result<()> {
-        let Some(source) = LyricSourceEnum::get_by_name(key_info.source_type.as_ref()) else {
-            return Err(format!("no source type is {}", key_info.source_type).into());
+    /// 获取歌词时不持有 `service` 的锁, 期间换了歌则放弃
+    pub async fn set_song_by_key(service: &Mutex<Self>, key_info: &SongInfoKey) -> Result<()> {
+        let (generation, source) = {
+            let this = service.lock().await;
+            let source = LyricSourceEnum::find(&this.sources, &key_info.source_type);
+            (this.generation, source)
         };
-        let lyric = source.fetch_lyrics(key_info.key.as_ref()).await?;
-        let lyric = TryInto::<Lyric>::try_into(lyric)?;
-        if let Some(save_key) = &self.now_save_cache {
-            Self::save_lyric(save_key, &lyric)
+        let (lyric, origin) = Self::fetch_by_key(source, key_info).await?;
+
+        let mut this = service.lock().await;
+        if this.generation != generation {
+            return Err("歌曲已切换".into());
+        }
+        if let Some(save_key) = &this.now_save_cache {
+            Self::save_lyric(save_key, &lyric, Some(&origin), true)
                 .await
                 .inspect_err(|err| error!("存储缓存异常: {}", err))?;
         }
-        self.now_lyric = Some(lyric);
-        _ = self.time_next(0);
-        Ok(())
+        this.now_lyric = Some(lyric);
+        this.now_lyric_origin = Some(origin);
+        this.push_all_lyric().await
+    }
+
+    async fn fetch_by_key(
+        source: Option<LyricSourceEnum>,
+        key_info: &SongInfoKey,
+    ) -> Result<(Lyric, LyricOrigin)> {
+        let source = source.ok_or_else(|| format!("no source type is {}", key_info.source_type))?;
+        let lyric: Lyric = source.fetch_lyrics(&key_info.key).await?.try_into()?;
+        let origin = LyricOrigin {
+            lyric: key_info.clone(),
+            trans: lyric.has_translation().then(|| key_info.clone()),
+        };
+        Ok((lyric, origin))
+    }
+
+    /// 为任意谱面手动指定歌词, 是当前歌曲时同时替换当前歌词
+    pub async fn assign_lyric(service: &Mutex<Self>, request: &AssignLyricRequest) -> Result<()> {
+        let (lyric, origin) = match (&request.lyric, &request.text) {
+            (Some(key_info), None) => {
+                let source = {
+                    let this = service.lock().await;
+                    LyricSourceEnum::find(&this.sources, &key_info.source_type)
+                };
+                let (lyric, origin) = Self::fetch_by_key(source, key_info).await?;
+                (lyric, Some(origin))
+            }
+            (None, Some(text)) => {
+                let lyric = Lyric::parse(text, request.trans_text.as_deref(), None)?;
+                (lyric, None)
+            }
+            _ => return Err("lyric and text must be provided exactly one".into()),
+        };
+
+        // 获取歌词时不持有锁, 写入前再确认是不是当前歌曲
+        let mut this = service.lock().await;
+        if this.is_current(request.bid) {
+            let song = this.now_save_cache.as_ref().ok_or("no save cache is set")?;
+            Self::save_lyric(song, &lyric, origin.as_ref(), true).await?;
+            this.now_lyric = Some(lyric);
+            this.now_lyric_origin = origin;
+            return this.push_all_lyric().await;
+        }
+        drop(this);
+
+        let mut song = Self::find_beatmap(request.bid).await?;
+        if let Some(sid) = request.sid {
+            song.sid = i64::from(sid);
+        }
+        if let Some(title) = &request.title {
+            song.title = title.clone();
+            song.title_unicode = title.clone();
+        }
+        if let Some(artist) = &request.artist {
+            song.artist = artist.clone();
+            song.artist_unicode = artist.clone();
+        }
+        if song.sid <= 0 {
+            return Err(format!("beatmap {} is unknown, sid is required", request.bid).into());
+        }
+        Self::save_lyric(&song, &lyric, origin.as_ref(), true).await
+    }
+
+    /// 手动搜索, 不影响自动搜索的结果, 也不需要持有服务的锁
+    pub async fn search_by_keyword(request: &SearchLyricRequest) -> Result<SearchLyricResult> {
+        let Some(source) = LyricSourceEnum::get_by_name(&request.source) else {
+            return Err(format!("no source type is {}", request.source).into());
+        };
+        let keyword = request.keyword.trim();
+        if keyword.is_empty() {
+            return Err("keyword is empty".into());
+        }
+        if request.page > MAX_SEARCH_PAGE {
+            return Err(format!("page must not exceed {MAX_SEARCH_PAGE}").into());
+        }
+        let page = request.page.max(1);
+        let limit = request.limit.clamp(1, MAX_SEARCH_LIMIT);
+        let songs = source.search_page(keyword, page, limit).await?;
+        Ok(SearchLyricResult {
+            source: source.name().to_string(),
+            page,
+            songs,
+        })
+    }
+
+    pub fn get_source_status() -> Vec<SourceStatus> {
+        LyricSourceEnum::all()
+            .iter()
+            .map(|source| source.guard().status())
+            .collect()
     }
 
     pub fn get_now_all_lyrics(&self) -> Option<&[LyricLine]> {
         self.now_lyric.as_ref().map(Lyric::get_lyrics)
     }
 
-    pub async fn set_block(&mut self, block: bool) -> Result<()> {
-        let Some(key) = self.now_save_cache.take() else {
-            return Err("no save cache is set".into());
+    /// 当前歌词的来源, 从缓存中读取, 没有歌词时返回 `None`
+    pub async fn get_lyric_info(&self) -> Result<Option<LyricInfo>> {
+        let (Some(song), Some(_)) = (&self.now_save_cache, &self.now_lyric) else {
+            return Ok(None);
         };
+        match Self::find_cached(song).await? {
+            Some(CachedLyric::Beatmap(cache)) => return Ok(Some(cache_info(cache))),
+            Some(CachedLyric::Shared(shared)) => return Ok(Some(shared_info(song, shared))),
+            None => {}
+        }
 
-        let (is_block, offset) =
-            match LyricConfigEntity::get_by_bid(key.bid as i32).await? {
-                Some((config_block, offset)) => (config_block, offset),
-                None => (false, 0),
-            };
+        // 写入缓存失败时只有内存中的来源
+        let origin = self.now_lyric_origin.as_ref();
+        let trans = origin.and_then(|origin| origin.trans.as_ref());
+        Ok(Some(LyricInfo {
+            bid: song.bid as u32,
+            sid: song.sid as u32,
+            title: song.title.clone(),
+            artist: Some(song.artist.clone()),
+            source: origin.map(|origin| origin.lyric.source_type.clone()),
+            source_key: origin.map(|origin| origin.lyric.key.clone()),
+            trans_source: trans.map(|key| key.source_type.clone()),
+            trans_source_key: trans.map(|key| key.key.clone()),
+            fetched_at: None,
+            manual: false,
+            pinned: false,
+        }))
+    }
 
-        if is_block == block {
-            return Ok(());
+    /// 取消拉黑后重新搜索歌词, 搜索时不持有 `service` 的锁
+    pub async fn set_block(service: &Mutex<Self>, block: bool) -> Result<()> {
+        let song = service.lock().await.update_block(block).await?;
+        if let Some(song) = song {
+            Self::song_change(service, song).await?;
         }
+        Ok(())
+    }
+
+    /// 拉黑或取消拉黑指定谱面, 是当前歌曲时同 [`Self::set_block`]
+    pub async fn set_block_by_bid(service: &Mutex<Self>, bid: u32, block: bool) -> Result<()> {
+        let is_current = service.lock().await.is_current(bid);
+        if is_current {
+            return Self::set_block(service, block).await;
+        }
+        let song = Self::find_known_beatmap(bid).await?;
+        Self::apply_block(&song, block).await?;
+        Ok(())
+    }
+
+    fn is_current(&self, bid: u32) -> bool {
+        self.now_save_cache
+            .as_ref()
+            .is_some_and(|now| now.bid == i64::from(bid))
+    }
 
+    /// 不是当前歌曲的谱面, 从缓存或设置中找到 sid/标题/艺术家, 都没有时只有 bid
+    async fn find_beatmap(bid: u32) -> Result<OsuSongInfo> {
+        let (sid, title, artist) = match LyricCacheEntity::find_by_bid(bid as i32).await? {
+            Some(cache) => (cache.sid, cache.title, cache.artist.unwrap_or_default()),
+            None => match LyricConfigEntity::find_by_bid(bid as i32).await? {
+                Some(config) => (config.sid, config.title, String::new()),
+                None => (0, String::new(), String::new()),
+            },
+        };
+        Ok(OsuSongInfo {
+            bid: i64::from(bid),
+            sid: i64::from(sid),
+            length: -1,
+            now: 0,
+            artist: artist.clone(),
+            artist_unicode: artist,
+            title: title.clone(),
+            title_unicode: title,
+            browsing: false,
+            audio_hash: None,
+            audio_path: None,
+        })
+    }
+
+    /// 同 [`Self::find_beatmap`], 不知道 sid 时返回错误
+    ///
+    /// 只有 bid 的设置没有标题和 sid, 不能写入, 否则可能按标题或 sid 匹配到无关的谱面
+    async fn find_known_beatmap(bid: u32) -> Result<OsuSongInfo> {
+        let song = Self::find_beatmap(bid).await?;
+        if song.sid <= 0 {
+            return Err(format!("beatmap {bid} is unknown, play it first").into());
+        }
+        Ok(song)
+    }
+
+    /// 返回需要重新搜索歌词的歌曲
+    async fn update_block(&mut self, block: bool) -> Result<Option<OsuSongInfo>> {
+        let Some(key) = self.now_save_cache.take() else {
+            return Err("no save cache is set".into());
+        };
+        let research = Self::apply_block(&key, block).await;
         if block {
-            LyricConfigEntity::save_setting(
-                key.bid as i32,
-                key.sid as i32,
-                &key.title,
-                true,
-                offset,
-            )
-            .await?;
             self.now_lyric.take();
-            self.now_save_cache = Some(key);
-        } else {
-            if offset == 0 {
-                LyricConfigEntity::delete_by_bid(key.bid as i32).await?;
-            } else {
+        }
+        match research {
+            Ok(true) => Ok(Some(key)),
+            Ok(false) => {
+                self.now_save_cache = Some(key);
+                Ok(None)
+            }
+            Err(err) => {
+                self.now_save_cache = Some(key);
+                Err(err)
+            }
+        }
+    }
+
+    /// 返回是否从拉黑变为不拉黑
+    async fn apply_block(key: &OsuSongInfo, block: bool) -> Result<bool> {
+        let (is_block, offset) = match LyricConfigEntity::get_by_bid(key.bid as i32).await? {
+            Some((config_block, offset)) => (config_block, offset),
+            None => (false, 0),
+        };
+
+        if block {
+            // 之前加的例外不再需要
+            block_service::remove_bid_exception(key.bid).await?;
+            if !is_block {
                 LyricConfigEntity::save_setting(
                     key.bid as i32,
                     key.sid as i32,
                     &key.title,
-                    false,
+                    true,
                     offset,
                 )
                 .await?;
             }
-            self.song_change(key).await?;
+            return Ok(false);
         }
-        Ok(())
+
+        let was_blocked = block_service::is_blocked(key).await?;
+        if is_block {
+            LyricConfigEntity::save_setting(
+                key.bid as i32,
+                key.sid as i32,
+                &key.title,
+                false,
+                offset,
+            )
+            .await?;
+            LyricConfigEntity::delete_if_default(key.bid as i32).await?;
+        }
+        // 仍被范围更大的规则拉黑时, 为这张谱面加一条例外
+        if block_service::is_blocked(key).await? {
+            let exception = BlockRuleRequest {
+                scope: BlockScope::Bid,
+                value: key.bid.to_string(),
+                block: false,
+            };
+            block_service::add_rule(exception).await?;
+        }
+        Ok(was_blocked)
     }
 
     pub async fn set_offset(&mut self, offset: i32) {
@@ -467,48 +1050,339 @@ impl LyricService {
         let Some(key) = self.now_save_cache.as_ref() else {
             return;
         };
-        let config = LyricConfigEntity::get_by_bid(key.bid as i32).await;
-        match config {
-            Ok(None) | Ok(Some((false, _))) if offset == 0 => {
-                let _ = LyricConfigEntity::delete_by_bid(key.bid as i32).await;
-            }
-            Ok(Some((disable, _))) => {
-                let _ = LyricConfigEntity::save_setting(
-                    key.bid as i32,
-                    key.sid as i32,
-                    &key.title,
-                    disable,
-                    offset,
-                )
-                .await;
-            }
-            Ok(None) => {
-                let _ = LyricConfigEntity::save_setting(
-                    key.bid as i32,
-                    key.sid as i32,
-                    &key.title,
-                    false,
-                    offset,
-                )
-                .await;
+        Self::save_offset(key, offset)
+            .await
+            .unwrap_or_else(|err| error!("保存偏移异常: {}", err));
+    }
+
+    /// 修改指定谱面的偏移, 是当前歌曲时同 [`Self::set_offset`]
+    pub async fn set_offset_by_bid(service: &Mutex<Self>, bid: u32, offset: i32) -> Result<()> {
+        {
+            let mut this = service.lock().await;
+            if this.is_current(bid) {
+                this.set_offset(offset).await;
+                return Ok(());
             }
-            Err(_) => {}
         }
+        let song = Self::find_known_beatmap(bid).await?;
+        Self::save_offset(&song, offset).await
+    }
+
+    async fn save_offset(key: &OsuSongInfo, offset: i32) -> Result<()> {
+        let bid = key.bid as i32;
+        let disable = match LyricConfigEntity::get_by_bid(bid).await? {
+            Some((disable, _)) => disable,
+            None if offset == 0 => return Ok(()),
+            None => false,
+        };
+        LyricConfigEntity::save_setting(bid, key.sid as i32, &key.title, disable, offset).await?;
+        // 偏移归零且没有其它设置时删除
+        LyricConfigEntity::delete_if_default(bid).await
+    }
+
+    pub async fn get_offset_list(query: &OffsetQuery) -> Result<OffsetListResult> {
+        let page = query.page.max(1);
+        let limit = query.limit.clamp(1, MAX_LIST_PAGE_SIZE);
+        let (models, total) =
+            LyricConfigEntity::find_offsets(u64::from(page - 1), u64::from(limit)).await?;
+        let items = models
+            .into_iter()
+            .map(|model| OffsetItem {
+                bid: model.bid as u32,
+                sid: model.sid as u32,
+                title: model.title,
+                offset: model.offset,
+                scale: model.scale,
+            })
+            .collect();
+        Ok(OffsetListResult { page, total, items })
     }
 
     pub fn get_offset(&self) -> i32 {
         self.offset
     }
 
-    async fn save_lyric(this: &OsuSongInfo, lyric: &Lyric) -> Result<()> {
-        LyricCacheEntity::save(
-            this.sid as i32,
-            this.bid as i32,
-            this.title.as_ref(),
-            this.length,
-            lyric,
+    /// 当前歌词的偏移, 分别列出各部分
+    pub fn get_lyric_offset(&self) -> LyricOffset {
+        LyricOffset {
+            total: self.total_offset(),
+            global: self.offset_setting.global,
+            source: self.source().map(str::to_string),
+            source_offset: self.source_offset(),
+            beatmap: self.offset,
+        }
+    }
+
+    pub fn get_offset_setting(&self) -> &OffsetSetting {
+        &self.offset_setting
+    }
+
+    /// 从数据库读取全局和歌词源的偏移
+    pub async fn load_offset_setting(&mut self) -> Result<()> {
+        self.offset_setting = SettingEntity::get_config(OFFSET_SETTING_KEY)
+            .await?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or_default();
+        Ok(())
+    }
+
+    pub async fn set_global_offset(&mut self, offset: i32) -> Result<&OffsetSetting> {
+        let mut setting = self.offset_setting.clone();
+        setting.global = offset;
+        self.save_offset_setting(setting).await
+    }
+
+    pub async fn set_source_offset(
+        &mut self,
+        request: &SourceOffsetRequest,
+    ) -> Result<&OffsetSetting> {
+        if LyricSourceEnum::get_by_name(&request.source).is_none() {
+            return Err(format!("未知的歌词源: {}", request.source).into());
+        }
+        let mut setting = self.offset_setting.clone();
+        if request.offset == 0 {
+            setting.sources.remove(&request.source);
+        } else {
+            setting
+                .sources
+                .insert(request.source.clone(), request.offset);
+        }
+        self.save_offset_setting(setting).await
+    }
+
+    async fn save_offset_setting(&mut self, setting: OffsetSetting) -> Result<&OffsetSetting> {
+        SettingEntity::save_config(
+            OFFSET_SETTING_KEY.to_string(),
+            serde_json::to_string(&setting)?,
+        )
+        .await?;
+        self.offset_setting = setting;
+        // 和 set_offset 一样重置时间窗口
+        self.current_lyric_start_time = -1;
+        self.current_lyric_end_time = -1;
+        Ok(&self.offset_setting)
+    }
+
+    /// 把当前播放位置设为第 `index` 行的开始时间
+    ///
+    /// 和上一个同步点相隔足够远时按两点重新计算倍率, 否则只修改偏移
+    pub async fn set_sync_anchor(&mut self, index: usize) -> Result<LyricSync> {
+        let lyric = self.now_lyric.as_ref().ok_or("当前没有歌词")?;
+        let line = lyric.get_line_by_index(index).ok_or("歌词行不存在")?;
+        let anchor = SyncAnchor {
+            play_time: self.play_time.max(0),
+            lyric_time: (line.time * 1000f32).round() as i32,
+        };
+        let previous = self.get_lyric_sync().await?.anchor;
+        let scale = match previous {
+            Some(previous)
+                if (anchor.play_time - previous.play_time).abs() >= MIN_ANCHOR_DISTANCE =>
+            {
+                f64::from(anchor.lyric_time - previous.lyric_time)
+                    / f64::from(anchor.play_time - previous.play_time)
+            }
+            _ => self.scale,
+        };
+        check_scale(scale)?;
+        // 只调整谱面的偏移, 全局和歌词源的偏移不变
+        let offset = anchor.lyric_time
+            - (f64::from(anchor.play_time) * scale).round() as i32
+            - (self.total_offset() - self.offset);
+        self.save_sync(offset, scale, Some(anchor)).await
+    }
+
+    /// 手动设置倍率, 保留偏移和同步点
+    pub async fn set_lyric_scale(&mut self, scale: f64) -> Result<LyricSync> {
+        check_scale(scale)?;
+        let anchor = self.get_lyric_sync().await?.anchor;
+        self.save_sync(self.offset, scale, anchor).await
+    }
+
+    /// 倍率恢复为 1 并清除同步点, 保留偏移
+    pub async fn clear_lyric_sync(&mut self) -> Result<LyricSync> {
+        let sync = self.save_sync(self.offset, 1.0, None).await?;
+        if let Some(key) = &self.now_save_cache {
+            LyricConfigEntity::delete_if_default(key.bid as i32).await?;
+        }
+        Ok(sync)
+    }
+
+    pub async fn get_lyric_sync(&self) -> Result<LyricSync> {
+        let key = self.now_save_cache.as_ref().ok_or("no save cache is set")?;
+        let anchor = LyricConfigEntity::find_by_bid(key.bid as i32)
+            .await?
+            .and_then(|model| {
+                Some(SyncAnchor {
+                    play_time: model.anchor_play_time?,
+                    lyric_time: model.anchor_lyric_time?,
+                })
+            });
+        Ok(LyricSync {
+            offset: self.offset,
+            scale: self.scale,
+            anchor,
+        })
+    }
+
+    async fn save_sync(
+        &mut self,
+        offset: i32,
+        scale: f64,
+        anchor: Option<SyncAnchor>,
+    ) -> Result<LyricSync> {
+        let key = self.now_save_cache.as_ref().ok_or("no save cache is set")?;
+        LyricConfigEntity::save_sync(
+            key.bid as i32,
+            key.sid as i32,
+            &key.title,
+            offset,
+            (scale != 1.0).then_some(scale),
+            anchor.map(|anchor| (anchor.play_time, anchor.lyric_time)),
+        )
+        .await?;
+        self.offset = offset;
+        self.scale = scale;
+        // 和 set_offset 一样重置时间窗口
+        self.current_lyric_start_time = -1;
+        self.current_lyric_end_time = -1;
+        Ok(LyricSync {
+            offset,
+            scale,
+            anchor,
+        })
+    }
+
+    /// `manual` 为 `true` 表示控制端手动选择的歌词
+    pub(super) async fn save_lyric(
+        this: &OsuSongInfo,
+        lyric: &Lyric,
+        origin: Option<&LyricOrigin>,
+        manual: bool,
+    ) -> Result<()> {
+        // 自动获取的歌词不覆盖手动选择或固定的缓存
+        let cache = LyricCacheEntity::find_by_bid(this.bid as i32).await?;
+        if manual || !cache.is_some_and(|cache| cache.manual || cache.pinned) {
+            LyricCacheEntity::save(this, lyric, origin, manual).await?;
+        }
+        if let Some(audio_hash) = &this.audio_hash {
+            // 自动获取的歌词不覆盖相同音频手动选择或固定的
+            let shared = SharedLyricEntity::find_by_hash(audio_hash).await?;
+            if manual || !shared.is_some_and(|shared| shared.manual || shared.pinned) {
+                let id = SharedLyricEntity::save(audio_hash, lyric, origin, manual).await?;
+                BeatmapLyricEntity::link(this, id).await?;
+            }
+        }
+        // 有歌词了, 之前没找到的记录作废
+        LyricMissEntity::delete_by_bid(this.bid as i32).await
+    }
+
+    async fn save_miss(this: &OsuSongInfo, instrumental: bool) -> Result<()> {
+        if Self::miss_retry() == 0 {
+            return Ok(());
+        }
+        debug!("记录没有歌词 {}", this.title_unicode);
+        LyricMissEntity::save(this, instrumental).await
+    }
+
+    /// 是否有还在有效期内的无歌词记录
+    async fn find_miss(bid: i32, sid: i32) -> Result<bool> {
+        let hours = Self::miss_retry();
+        if hours == 0 {
+            return Ok(false);
+        }
+        let since = Utc::now()
+            .timestamp()
+            .saturating_sub(Self::miss_retry_secs());
+        Ok(LyricMissEntity::find_valid(bid, sid, since)
+            .await?
+            .is_some())
+    }
+
+    /// 没有歌词的谱面隔多久重新搜索, 小时, 0 表示不记录
+    pub fn miss_retry() -> u64 {
+        MISS_RETRY_HOURS.load(Ordering::SeqCst)
+    }
+
+    fn miss_retry_secs() -> i64 {
+        // 存入时已限制上限, 不会溢出
+        (Self::miss_retry().min(MAX_MISS_RETRY_HOURS) * 3600) as i64
+    }
+
+    /// 从数据库读取重新搜索的间隔
+    pub async fn load_miss_retry() -> Result<()> {
+        let hours = SettingEntity::get_config(MISS_RETRY_SETTING_KEY)
+            .await?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or(DEFAULT_MISS_RETRY_HOURS)
+            .min(MAX_MISS_RETRY_HOURS);
+        MISS_RETRY_HOURS.store(hours, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub async fn set_miss_retry(hours: u64) -> Result<()> {
+        if hours > MAX_MISS_RETRY_HOURS {
+            return Err(format!("重新搜索的间隔不能超过 {MAX_MISS_RETRY_HOURS} 小时").into());
+        }
+        SettingEntity::save_config(
+            MISS_RETRY_SETTING_KEY.to_string(),
+            serde_json::to_string(&hours)?,
+        )
+        .await?;
+        MISS_RETRY_HOURS.store(hours, Ordering::SeqCst);
+        Ok(())
+    }
+
+    /// 自动获取的歌词缓存的有效期, 小时, 0 表示不过期
+    pub fn cache_ttl() -> u64 {
+        CACHE_TTL_HOURS.load(Ordering::SeqCst)
+    }
+
+    /// 从数据库读取缓存的有效期
+    pub async fn load_cache_ttl() -> Result<()> {
+        let hours = SettingEntity::get_config(CACHE_TTL_SETTING_KEY)
+            .await?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or(0)
+            .min(MAX_CACHE_TTL_HOURS);
+        CACHE_TTL_HOURS.store(hours, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub async fn set_cache_ttl(hours: u64) -> Result<()> {
+        if hours > MAX_CACHE_TTL_HOURS {
+            return Err(format!("缓存有效期不能超过 {MAX_CACHE_TTL_HOURS} 小时").into());
+        }
+        SettingEntity::save_config(
+            CACHE_TTL_SETTING_KEY.to_string(),
+            serde_json::to_string(&hours)?,
         )
-        .await
+        .await?;
+        CACHE_TTL_HOURS.store(hours, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub async fn get_miss_list() -> Result<Vec<MissItem>> {
+        let retry = Self::miss_retry_secs();
+        Ok(LyricMissEntity::find_all()
+            .await?
+            .into_iter()
+            .map(|m| MissItem {
+                bid: m.bid as u32,
+                sid: m.sid as u32,
+                title: m.title,
+                instrumental: m.instrumental,
+                checked_at: m.checked_at,
+                retry_at: m.checked_at.saturating_add(retry),
+            })
+            .collect())
+    }
+
+    /// `bid` 为 `None` 时清空全部
+    pub async fn clear_miss(bid: Option<i32>) -> Result<()> {
+        match bid {
+            Some(bid) => LyricMissEntity::delete_by_bid(bid).await,
+            None => LyricMissEntity::delete_all().await,
+        }
     }
 
     pub async fn get_all_block_list(&self) -> Result<Vec<BlockItem>> {
@@ -527,8 +1401,134 @@ impl LyricService {
         LyricCacheEntity::all_count().await
     }
 
-    pub async fn clear_all_cache(&self) -> Result<()> {
-        LyricCacheEntity::delete_all().await
+    /// `force` 为 `false` 时保留固定的缓存和它们用到的共享歌词
+    pub async fn clear_all_cache(&self, force: bool) -> Result<()> {
+        if force {
+            LyricCacheEntity::delete_all().await?;
+            SharedLyricEntity::delete_all().await?;
+            return BeatmapLyricEntity::delete_all().await;
+        }
+        LyricCacheEntity::delete_unpinned().await?;
+        let shared = SharedLyricEntity::delete_unpinned().await?;
+        if !shared.is_empty() {
+            BeatmapLyricEntity::delete_by_lyric_ids(shared).await?;
+        }
+        Ok(())
+    }
+
+    /// 固定或取消固定一条缓存, 用到的共享歌词一起修改;
+    /// 只通过共享歌词显示的谱面固定共享歌词
+    pub async fn set_cache_pin(pin: &CachePin) -> Result<()> {
+        let bid = pin.bid as i32;
+        let cached = LyricCacheEntity::set_pinned(bid, pin.pinned).await?;
+        let shared = BeatmapLyricEntity::find_lyric_ids(Some(bid), None).await?;
+        if cached == 0 && shared.is_empty() {
+            return Err(format!("没有 {} 的缓存", pin.bid).into());
+        }
+        if !shared.is_empty() {
+            SharedLyricEntity::set_pinned(shared, pin.pinned).await?;
+        }
+        Ok(())
+    }
+
+    /// 分页列出缓存, 可以按标题/艺术家/bid 过滤
+    ///
+    /// 没有自己的缓存、只通过共享歌词显示的谱面排在缓存后面
+    pub async fn find_cache(query: &CacheQuery) -> Result<CacheListResult> {
+        let page = query.page.max(1);
+        let limit = query.limit.clamp(1, MAX_LIST_PAGE_SIZE);
+        let filter = |text: &Option<String>| {
+            text.as_deref()
+                .map(str::trim)
+                .filter(|text| !text.is_empty())
+                .map(str::to_string)
+        };
+        let (title, artist) = (filter(&query.title), filter(&query.artist));
+        let bid = query.bid.map(|bid| bid as i32);
+        let (models, cache_total) = LyricCacheEntity::find_by_title_like(
+            title.as_deref(),
+            artist.as_deref(),
+            bid,
+            u64::from(page - 1),
+            u64::from(limit),
+        )
+        .await?;
+        // 缓存不够一页时接着列出共享歌词
+        let offset = u64::from(page - 1) * u64::from(limit);
+        let (links, link_total) = BeatmapLyricEntity::find_uncached(
+            title.as_deref(),
+            artist.as_deref(),
+            bid,
+            offset.saturating_sub(cache_total),
+            u64::from(limit) - models.len() as u64,
+        )
+        .await?;
+        let mut items = models.into_iter().map(cache_info).collect::<Vec<_>>();
+        if !links.is_empty() {
+            let ids = links.iter().map(|link| link.lyric_id).collect();
+            let shared = SharedLyricEntity::find_by_ids(ids).await?;
+            items.extend(links.into_iter().filter_map(|link| {
+                let shared = shared.iter().find(|shared| shared.id == link.lyric_id)?;
+                Some(link_info(link, shared.clone()))
+            }));
+        }
+        Ok(CacheListResult {
+            page,
+            total: cache_total + link_total,
+            items,
+        })
+    }
+
+    /// 返回删除的谱面数量, 同时停止谱面使用共享歌词, 之后按音频也不会再找到它,
+    /// 没有其他谱面使用的共享歌词一起删除
+    ///
+    /// 不是 `force` 时跳过固定的缓存和共享歌词
+    pub async fn delete_cache(key: &CacheKey) -> Result<u64> {
+        let (bid, sid) = (key.bid.map(|bid| bid as i32), key.sid.map(|sid| sid as i32));
+        let mut deleted = match (bid, sid) {
+            (Some(bid), _) => LyricCacheEntity::delete_by_bid(bid, key.force).await?,
+            (None, Some(sid)) => LyricCacheEntity::delete_by_sid(sid, key.force).await?,
+            (None, None) => return Err("bid 和 sid 不能都为空".into()),
+        };
+        let mut shared = BeatmapLyricEntity::find_lyric_ids(bid, sid).await?;
+        if !key.force && !shared.is_empty() {
+            shared = SharedLyricEntity::filter_unpinned(shared).await?;
+        }
+        if !shared.is_empty() {
+            // 保留记录, 避免按音频再找到这份歌词; 其他谱面集还在使用的共享歌词保留
+            for detached in BeatmapLyricEntity::detach(bid, sid, shared.clone()).await? {
+                // 固定的缓存还在, 只是不再使用共享歌词, 不算删除
+                if !deleted.contains(&detached)
+                    && LyricCacheEntity::find_by_bid(detached).await?.is_none()
+                {
+                    deleted.push(detached);
+                }
+            }
+            let unused = BeatmapLyricEntity::filter_unused(shared).await?;
+            if !unused.is_empty() {
+                SharedLyricEntity::delete_by_ids(unused.clone()).await?;
+                BeatmapLyricEntity::delete_by_lyric_ids(unused).await?;
+            }
+        }
+        Ok(deleted.len() as u64)
+    }
+
+    /// 任意一条缓存的完整歌词, 没有缓存时看谱面使用的共享歌词
+    pub async fn get_cache_lyric(bid: u32) -> Result<Vec<LyricLine>> {
+        let lyric: Lyric = match LyricCacheEntity::find_by_bid(bid as i32).await? {
+            Some(cache) => (&cache).try_into()?,
+            None => {
+                let link = BeatmapLyricEntity::find_by_bid(bid as i32)
+                    .await?
+                    .ok_or_else(|| format!("没有 {bid} 的缓存"))?;
+                let shared = SharedLyricEntity::find_by_ids(vec![link.lyric_id])
+                    .await?
+                    .pop()
+                    .ok_or_else(|| format!("没有 {bid} 的缓存"))?;
+                CachedLyric::Shared(shared).lyric()?
+            }
+        };
+        Ok(lyric.get_lyrics().to_vec())
     }
 }
 
@@ -537,3 +1537,1360 @@ impl Default for LyricService {
         Self::new()
     }
 }
+
+/// [`LyricService::cache_song`] 的结果
+pub(super) enum CacheOutcome {
+    Blocked,
+    /// 已有缓存
+    Cached,
+    /// 之前没有找到歌词, 还没到重新搜索的时间
+    Missed,
+    NotFound,
+    /// 有歌词源请求失败, 没有记录结果
+    Failed,
+    Saved(Lyric, LyricOrigin),
+}
+
+/// 一次网络搜索的结果
+pub(super) enum SearchOutcome {
+    Found(Lyric, LyricOrigin),
+    /// 所有源都没有歌词
+    NotFound,
+    /// 没有歌词, 且有源标记为纯音乐
+    Instrumental,
+    /// 没有找到, 但有源请求失败, 结果不可信
+    Failed,
+}
+
+/// 本地缓存的歌词
+enum CachedLyric {
+    /// 谱面自己的缓存
+    Beatmap(LyricCacheModel),
+    /// 相同音频共用的歌词
+    Shared(SharedLyricModel),
+}
+
+impl CachedLyric {
+    fn lyric(&self) -> Result<Lyric> {
+        match self {
+            Self::Beatmap(cache) => Lyric::from_json_cache(&cache.cache),
+            Self::Shared(shared) => Lyric::from_json_cache(&shared.cache),
+        }
+    }
+
+    fn origin(&self) -> Option<LyricOrigin> {
+        match self {
+            Self::Beatmap(cache) => cached_origin(
+                &cache.source,
+                &cache.source_key,
+                &cache.trans_source,
+                &cache.trans_source_key,
+            ),
+            Self::Shared(shared) => cached_origin(
+                &shared.source,
+                &shared.source_key,
+                &shared.trans_source,
+                &shared.trans_source_key,
+            ),
+        }
+    }
+
+    /// 手动选择或固定的歌词, 不会过期也不会被自动获取的覆盖
+    fn is_kept(&self) -> bool {
+        match self {
+            Self::Beatmap(cache) => cache.manual || cache.pinned,
+            Self::Shared(shared) => shared.manual || shared.pinned,
+        }
+    }
+
+    /// 自动获取的歌词超过有效期 `hours` 小时, 0 表示不过期
+    fn is_expired(&self, hours: u64) -> bool {
+        if hours == 0 || self.is_kept() {
+            return false;
+        }
+        let fetched_at = match self {
+            Self::Beatmap(cache) => cache.fetched_at,
+            Self::Shared(shared) => shared.fetched_at,
+        };
+        // 旧版本的缓存没有获取时间, 视为已过期
+        let Some(fetched_at) = fetched_at else {
+            return true;
+        };
+        let ttl = (hours.min(MAX_CACHE_TTL_HOURS) * 3600) as i64;
+        fetched_at.saturating_add(ttl) <= Utc::now().timestamp()
+    }
+}
+
+fn shared_info(song: &OsuSongInfo, shared: SharedLyricModel) -> LyricInfo {
+    LyricInfo {
+        bid: song.bid as u32,
+        sid: song.sid as u32,
+        title: song.title.clone(),
+        artist: Some(song.artist.clone()),
+        source: shared.source,
+        source_key: shared.source_key,
+        trans_source: shared.trans_source,
+        trans_source_key: shared.trans_source_key,
+        fetched_at: shared.fetched_at,
+        manual: shared.manual,
+        pinned: shared.pinned,
+    }
+}
+
+/// 没有自己的缓存, 通过共享歌词显示的谱面
+fn link_info(link: BeatmapLyricModel, shared: SharedLyricModel) -> LyricInfo {
+    LyricInfo {
+        bid: link.bid as u32,
+        sid: link.sid as u32,
+        title: link.title.unwrap_or_default(),
+        artist: link.artist,
+        source: shared.source,
+        source_key: shared.source_key,
+        trans_source: shared.trans_source,
+        trans_source_key: shared.trans_source_key,
+        fetched_at: shared.fetched_at,
+        manual: shared.manual,
+        pinned: shared.pinned,
+    }
+}
+
+fn cache_info(cache: LyricCacheModel) -> LyricInfo {
+    LyricInfo {
+        bid: cache.bid as u32,
+        sid: cache.sid as u32,
+        title: cache.title,
+        artist: cache.artist,
+        source: cache.source,
+        source_key: cache.source_key,
+        trans_source: cache.trans_source,
+        trans_source_key: cache.trans_source_key,
+        fetched_at: cache.fetched_at,
+        manual: cache.manual,
+        pinned: cache.pinned,
+    }
+}
+
+/// 获取过歌词的歌曲顺便记下歌词标记, 期间换了歌找不到就算了
+async fn record_lyric_flags(
+    cache: &Mutex<HashMap<&'static str, Vec<SongInfo>>>,
+    source: &str,
+    key: &str,
+    lyric: &LyricResult,
+) {
+    let mut cache_map = cache.lock().await;
+    let song = cache_map
+        .get_mut(source)
+        .and_then(|songs| songs.iter_mut().find(|song| song.key == key));
+    if let Some(song) = song {
+        song.set_lyric_flags(lyric);
+    }
+}
+
+/// 音频时长, 毫秒, 未知时为 0
+fn song_length(song: &OsuSongInfo) -> u32 {
+    if song.length < 0 {
+        0u32
+    } else {
+        song.length as u32
+    }
+}
+
+/// 缓存里记录的来源, 没有来源时为 `None`
+fn cached_origin(
+    source: &Option<String>,
+    source_key: &Option<String>,
+    trans_source: &Option<String>,
+    trans_source_key: &Option<String>,
+) -> Option<LyricOrigin> {
+    let key = |source: &Option<String>, key: &Option<String>| {
+        Some(SongInfoKey {
+            source_type: source.clone()?,
+            key: key.clone()?,
+        })
+    };
+    Some(LyricOrigin {
+        lyric: key(source, source_key)?,
+        trans: key(trans_source, trans_source_key),
+    })
+}
+
+fn check_scale(scale: f64) -> Result<()> {
+    if (MIN_SCALE..=MAX_SCALE).contains(&scale) {
+        Ok(())
+    } else {
+        Err(format!("倍率 {scale:.4} 超出范围 {MIN_SCALE}~{MAX_SCALE}").into())
+    }
+}
+
+/// 一次换歌需要的网络搜索, 执行时不依赖 `LyricService`
+struct SongSearch {
+    generation: u64,
+    title: String,
+    artist: String,
+    length: u32,
+    sources: Vec<LyricSourceEnum>,
+    music_cache: Arc<Mutex<HashMap<&'static str, Vec<SongInfo>>>>,
+    cancel_rx: broadcast::Receiver<()>,
+    /// 有源请求失败
+    failed: Arc<AtomicBool>,
+    /// 有源标记为纯音乐
+    instrumental: AtomicBool,
+}
+
+impl SongSearch {
+    /// 同时搜索所有源, 先搜到且歌词有效的源胜出, 其余源的任务通过返回的 `JoinSet` 在后台继续
+    async fn run(&self) -> (SearchOutcome, JoinSet<&'static str>) {
+        let mut join_set = JoinSet::new();
+        for source in &self.sources {
+            self.spawn_search_task(&mut join_set, *source);
+        }
+
+        while let Some(source_name) = join_set.join_next().await {
+            let Ok(source_name) = source_name else {
+                continue;
+            };
+            let Some(source) = LyricSourceEnum::find(&self.sources, source_name) else {
+                continue;
+            };
+            if let Some((lyric, origin)) = self.try_search_lyric(source).await {
+                return (SearchOutcome::Found(lyric, origin), join_set);
+            }
+        }
+        let outcome = if self.failed.load(Ordering::SeqCst) {
+            SearchOutcome::Failed
+        } else if self.instrumental.load(Ordering::SeqCst) {
+            SearchOutcome::Instrumental
+        } else {
+            SearchOutcome::NotFound
+        };
+        (outcome, join_set)
+    }
+
+    /// 单个源出错不影响其他源, 记录日志后视为没找到
+    async fn try_search_lyric(&self, source: LyricSourceEnum) -> Option<(Lyric, LyricOrigin)> {
+        self.search_lyric(source).await.unwrap_or_else(|err| {
+            warn!("{} 获取歌词失败: {}", source.name(), err);
+            self.failed.store(true, Ordering::SeqCst);
+            None
+        })
+    }
+
+    // 从 music_cache 中的搜索结果获取歌词
+    async fn search_lyric(&self, source: LyricSourceEnum) -> Result<Option<(Lyric, LyricOrigin)>> {
+        let songs_to_search = {
+            let cache = self.music_cache.lock().await;
+            cache.get(source.name()).cloned().unwrap_or_default()
+        };
+
+        if songs_to_search.is_empty() {
+            return Ok(None);
+        }
+
+        let lyric_result = source
+            .search_lyrics(&songs_to_search, &self.title, self.length, &self.artist)
+            .await?;
+
+        let Some((song, lyric_result)) = lyric_result else {
+            return Ok(None);
+        };
+        record_lyric_flags(&self.music_cache, source.name(), &song.key, &lyric_result).await;
+        if lyric_result.instrumental {
+            debug!("{} 标记为纯音乐 {}", source.name(), song.key);
+            self.instrumental.store(true, Ordering::SeqCst);
+            return Ok(None);
+        }
+        let has_trans = lyric_result.trans.is_some();
+        let mut lyric: Lyric = lyric_result.try_into()?;
+        let key = SongInfoKey {
+            source_type: source.name().to_string(),
+            key: song.key,
+        };
+        let trans = if has_trans {
+            Some(key.clone())
+        } else {
+            self.merge_translation(source.name(), &mut lyric).await
+        };
+        Ok(Some((lyric, LyricOrigin { lyric: key, trans })))
+    }
+
+    /// 选中的歌词没有翻译时, 从其他源找同一首歌的翻译合并进来, 返回翻译的来源
+    async fn merge_translation(&self, exclude: &str, lyric: &mut Lyric) -> Option<SongInfoKey> {
+        let (title, length, artist) = (self.title.as_str(), self.length, self.artist.as_str());
+        for source in &self.sources {
+            if source.name() == exclude {
+                continue;
+            }
+            let mut songs = {
+                let cache = self.music_cache.lock().await;
+                cache.get(source.name()).cloned().unwrap_or_default()
+            };
+            // 这个源的搜索任务可能还没完成
+            if songs.is_empty() {
+                songs = source
+                    .search_all_music(title, artist)
+                    .await
+                    .inspect_err(|err| warn!("{} 搜索翻译失败: {}", source.name(), err))
+                    .unwrap_or_default();
+            }
+
+            let candidates = LyricSourceEnum::preferred_song(&songs, title, length, artist)
+                .into_iter()
+                .filter(|song| length == 0 || song.length.abs_diff(length) <= MERGE_ALLOW_OFFSET)
+                .take(MERGE_CANDIDATES);
+            for song in candidates {
+                let result = match source.fetch_lyrics(&song.key).await {
+                    Ok(result) => result,
+                    Err(err) => {
+                        warn!("{} 获取翻译失败: {}", source.name(), err);
+                        continue;
+                    }
+                };
+                record_lyric_flags(&self.music_cache, source.name(), &song.key, &result).await;
+                if result.trans.is_none() {
+                    continue;
+                }
+                let Ok(other) = TryInto::<Lyric>::try_into(result) else {
+                    continue;
+                };
+                let count = lyric.merge_translation(&other);
+                if count > 0 {
+                    debug!("从 {} 合并了 {} 行翻译", source.name(), count);
+                    return Some(SongInfoKey {
+                        source_type: source.name().to_string(),
+                        key: song.key.clone(),
+                    });
+                }
+            }
+        }
+        None
+    }
+
+    fn spawn_search_task(&self, tasks: &mut JoinSet<&'static str>, source: LyricSourceEnum) {
+        let title = self.title.clone();
+        let artist = self.artist.clone();
+        let music_cache = Arc::clone(&self.music_cache);
+        let failed = Arc::clone(&self.failed);
+        let mut cancel_rx = self.cancel_rx.resubscribe();
+        tasks.spawn(async move {
+            // 使用 tokio::select! 同时监听取消信号和搜索结果
+            let search_result = tokio::select! {
+                // 取消分支 - 收到信号立即退出
+                _ = cancel_rx.recv() => {
+                    return source.name();
+                }
+                // 搜索分支 - 执行实际的搜索操作
+                result = source.search_all_music(&title, &artist) => result,
+            };
+
+            // 如果搜索成功（未被取消），处理结果
+            match search_result {
+                Ok(musics) => {
+                    let mut cache = music_cache.lock().await;
+                    if let Some(cache_vec) = cache.get_mut(source.name()) {
+                        cache_vec.extend(musics);
+                    }
+                }
+                Err(err) => {
+                    warn!("{} 搜索失败: {}", source.name(), err);
+                    failed.store(true, Ordering::SeqCst);
+                }
+            }
+
+            source.name()
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_test_database;
+    use crate::lyric::fixture::*;
+    use crate::service::fixture::TestService;
+    use tokio::sync::Notify;
+
+    #[tokio::test]
+    async fn test_time_update_not_stalled_by_search() -> Result<()> {
+        let gate = Arc::new(Notify::new());
+        let server = FixtureServer::start(vec![
+            Fixture::json(QQ_SEARCH_PATH, QQ_SEARCH).gate(Arc::clone(&gate)),
+            Fixture::json(QQ_LYRIC_PATH, QQ_LYRIC),
+        ])
+        .await;
+        let t = TestService::with_server(server, FixtureServer::qq_source).await;
+
+        let ticks = async {
+            // 等搜索请求发出, 在放行之前搜索一直没有完成
+            while t.server.requests().is_empty() {
+                tokio::task::yield_now().await;
+            }
+            for time in 0..5 {
+                let mut this = t.service.lock().await;
+                assert!(this.get_now_all_lyrics().is_none(), "搜索应该还没完成");
+                this.time_next(time * 100).await?;
+            }
+            gate.notify_one();
+            Ok::<_, Error>(())
+        };
+        let (changed, ticked) = tokio::join!(
+            LyricService::song_change(t.service, unique_song("Clair de lune")),
+            ticks
+        );
+        changed?;
+        ticked?;
+
+        let this = t.service.lock().await;
+        assert_eq!(this.get_now_all_lyrics().map(<[_]>::len), Some(6));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_instrumental_skips_search() -> Result<()> {
+        let server = FixtureServer::start(vec![
+            Fixture::json(NETEASE_SEARCH_PATH, NETEASE_SEARCH),
+            Fixture::json(NETEASE_LYRIC_PATH, NETEASE_PURE_MUSIC),
+        ])
+        .await;
+        let t = TestService::with_server(server, FixtureServer::netease_source).await;
+        let song = OsuSongInfo {
+            length: -1,
+            ..unique_song("Clair de lune")
+        };
+        let (bid, sid) = (song.bid as i32, song.sid as i32);
+
+        LyricService::song_change(t.service, song.clone()).await?;
+        assert!(t.service.lock().await.get_now_all_lyrics().is_none());
+        let miss = LyricMissEntity::find_valid(bid, sid, 0).await?;
+        assert!(miss.is_some_and(|miss| miss.instrumental));
+
+        // 再次游玩不再请求歌词源
+        let requests = t.server.requests().len();
+        LyricService::song_change(t.service, song.clone()).await?;
+        assert_eq!(t.server.requests().len(), requests);
+
+        // 清除后重新搜索
+        LyricService::clear_miss(Some(bid)).await?;
+        LyricService::song_change(t.service, song).await?;
+        assert!(t.server.requests().len() > requests);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_miss_retry_limit() -> Result<()> {
+        init_test_database().await;
+        let hours = LyricService::miss_retry();
+        assert!(LyricService::set_miss_retry(u64::MAX).await.is_err());
+        assert!(
+            LyricService::set_miss_retry(MAX_MISS_RETRY_HOURS + 1)
+                .await
+                .is_err()
+        );
+        // 没有修改
+        assert_eq!(LyricService::miss_retry(), hours);
+        Ok(())
+    }
+
+    /// 写入一条很久以前获取的缓存
+    async fn seed_cache(lyric: &str, manual: bool) -> Result<OsuSongInfo> {
+        let song = unique_song("Clair de lune");
+        let lyric = Lyric::parse(lyric, Some(lyric), None)?;
+        LyricService::save_lyric(&song, &lyric, None, manual).await?;
+        LyricCacheEntity::set_fetched_at(song.bid as i32, 0).await?;
+        Ok(song)
+    }
+
+    async fn find_cache(song: &OsuSongInfo) -> Result<LyricCacheModel> {
+        let cache = LyricCacheEntity::find_by_bid(song.bid as i32).await?;
+        Ok(cache.ok_or("no cache")?)
+    }
+
+    #[tokio::test]
+    async fn test_cache_expired() -> Result<()> {
+        init_test_database().await;
+        let old = find_cache(&seed_cache("[00:00.00]old", false).await?).await?;
+        assert!(CachedLyric::Beatmap(old.clone()).is_expired(1));
+        // 0 表示不过期
+        assert!(!CachedLyric::Beatmap(old.clone()).is_expired(0));
+        // 手动选择的不过期
+        let manual = find_cache(&seed_cache("[00:00.00]manual", true).await?).await?;
+        assert!(!CachedLyric::Beatmap(manual).is_expired(1));
+        let recent = LyricCacheModel {
+            fetched_at: Some(Utc::now().timestamp()),
+            ..old
+        };
+        assert!(!CachedLyric::Beatmap(recent).is_expired(1));
+        Ok(())
+    }
+
+    /// 和游玩时一样查找缓存, 按它的完整程度刷新
+    async fn refresh(t: &TestService, song: &OsuSongInfo) -> Result<()> {
+        let cached = LyricService::find_cached(song).await?.ok_or("no cache")?;
+        assert!(cached.is_expired(1));
+        let score = cached.lyric()?.score();
+        LyricService::refresh_cache(&t.sources, song, &cached, score).await
+    }
+
+    #[tokio::test]
+    async fn test_expired_cache_refresh() -> Result<()> {
+        let t = TestService::start().await;
+        let worse = seed_cache("[00:00.00]old", false).await?;
+        refresh(&t, &worse).await?;
+        let cache = find_cache(&worse).await?;
+        assert_eq!(cache.source.as_deref(), Some("QQ"));
+        assert_eq!(Lyric::from_json_cache(&cache.cache)?.get_lyrics().len(), 6);
+
+        // 新歌词不如缓存完整, 只更新获取时间
+        let better = (0..20)
+            .map(|i| format!("[00:{i:02}.00]line {i}\n"))
+            .collect::<String>();
+        let better = seed_cache(&better, false).await?;
+        refresh(&t, &better).await?;
+        let cache = find_cache(&better).await?;
+        assert!(cache.fetched_at > Some(0));
+        assert_eq!(cache.source, None);
+        assert_eq!(Lyric::from_json_cache(&cache.cache)?.get_lyrics().len(), 20);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_sibling_cache_refresh() -> Result<()> {
+        let t = TestService::start().await;
+        // 同一谱面集其他难度的缓存, 比新歌词完整, 只更新它的获取时间
+        let better = (0..20)
+            .map(|i| format!("[00:{i:02}.00]line {i}\n"))
+            .collect::<String>();
+        let sibling = seed_cache(&better, false).await?;
+        let song = OsuSongInfo {
+            sid: sibling.sid,
+            ..unique_song("Clair de lune")
+        };
+        refresh(&t, &song).await?;
+        assert!(find_cache(&sibling).await?.fetched_at > Some(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_expired_shared_lyric_refresh() -> Result<()> {
+        let t = TestService::start().await;
+        let (first, other) = same_audio_songs();
+        let lyric = Lyric::parse("[00:00.00]old", None, None)?;
+        LyricService::save_lyric(&first, &lyric, None, false).await?;
+        let audio_hash = first.audio_hash.as_deref().ok_or("no audio hash")?;
+        let shared = SharedLyricEntity::find_by_hash(audio_hash).await?;
+        let shared = shared.ok_or("no shared lyric")?;
+        SharedLyricEntity::set_fetched_at(shared.id, 0).await?;
+
+        // 只通过相同音频找到歌词的谱面, 共享的歌词一样会过期
+        refresh(&t, &other).await?;
+        let shared = SharedLyricEntity::find_by_hash(audio_hash).await?;
+        let shared = shared.ok_or("no shared lyric")?;
+        assert_eq!(shared.source.as_deref(), Some("QQ"));
+        assert!(shared.fetched_at > Some(0));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_cache_browse() -> Result<()> {
+        init_test_database().await;
+        let lyric = Lyric::parse("[00:00.00]line", None, None)?;
+        let title = "Gymnopedie browse";
+        let first = OsuSongInfo {
+            artist: "Erik Satie".to_string(),
+            ..unique_song(title)
+        };
+        let same_set = OsuSongInfo {
+            bid: unique_song(title).bid,
+            ..first.clone()
+        };
+        for song in [&first, &same_set, &unique_song(title)] {
+            LyricService::save_lyric(song, &lyric, None, false).await?;
+        }
+
+        let query = CacheQuery {
+            title: Some("nopedie brow".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(LyricService::find_cache(&query).await?.total, 3);
+        let query = CacheQuery {
+            title: Some(title.to_string()),
+            artist: Some("Satie".to_string()),
+            limit: 1,
+            page: 2,
+            ..Default::default()
+        };
+        let result = LyricService::find_cache(&query).await?;
+        assert_eq!((result.total, result.items.len()), (2, 1));
+        assert_eq!(result.items[0].artist.as_deref(), Some("Erik Satie"));
+
+        let lines = LyricService::get_cache_lyric(first.bid as u32).await?;
+        assert_eq!(lines[0].origin.as_deref(), Some("line"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_cache() -> Result<()> {
+        init_test_database().await;
+        let lyric = Lyric::parse("[00:00.00]line", None, None)?;
+        let first = unique_song("Clair de lune");
+        let same_set = OsuSongInfo {
+            sid: first.sid,
+            ..unique_song("Clair de lune")
+        };
+        let other = unique_song("Clair de lune");
+        for song in [&first, &same_set, &other] {
+            LyricService::save_lyric(song, &lyric, None, false).await?;
+        }
+
+        let sid = CacheKey {
+            sid: Some(first.sid as u32),
+            ..Default::default()
+        };
+        assert_eq!(LyricService::delete_cache(&sid).await?, 2);
+        let bid = CacheKey {
+            bid: Some(other.bid as u32),
+            ..Default::default()
+        };
+        assert_eq!(LyricService::delete_cache(&bid).await?, 1);
+        assert!(
+            LyricService::get_cache_lyric(other.bid as u32)
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    async fn pin(song: &OsuSongInfo, pinned: bool) -> Result<()> {
+        let pin = CachePin {
+            bid: song.bid as u32,
+            pinned,
+        };
+        LyricService::set_cache_pin(&pin).await
+    }
+
+    #[tokio::test]
+    async fn test_pinned_cache_kept() -> Result<()> {
+        let t = TestService::start().await;
+        let pinned = seed_cache("[00:00.00]pinned", false).await?;
+        // 开始刷新之后才固定
+        let cached = CachedLyric::Beatmap(find_cache(&pinned).await?);
+        pin(&pinned, true).await?;
+        assert!(pin(&unique_song("Clair de lune"), true).await.is_err());
+
+        // 固定的缓存不刷新, 也不被自动获取的歌词覆盖
+        LyricService::refresh_cache(&t.sources, &pinned, &cached, (0, 0)).await?;
+        let other = Lyric::parse("[00:00.00]auto\n[00:01.00]auto", None, None)?;
+        LyricService::save_lyric(&pinned, &other, None, false).await?;
+        let cache = find_cache(&pinned).await?;
+        assert_eq!((cache.fetched_at, cache.source), (Some(0), None));
+        assert_eq!(Lyric::from_json_cache(&cache.cache)?.get_lyrics().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_manual_cache_kept_after_unpin() -> Result<()> {
+        let t = TestService::start().await;
+        let manual = seed_cache("[00:00.00]manual", true).await?;
+        pin(&manual, false).await?;
+
+        // 取消固定后仍然是手动选择的, 刷新和预缓存的结果不覆盖
+        let cached = CachedLyric::Beatmap(find_cache(&manual).await?);
+        LyricService::refresh_cache(&t.sources, &manual, &cached, (0, 0)).await?;
+        let other = Lyric::parse("[00:00.00]auto\n[00:01.00]auto", None, None)?;
+        LyricService::save_lyric(&manual, &other, None, false).await?;
+        let cache = find_cache(&manual).await?;
+        assert!(cache.manual && !cache.pinned);
+        assert_eq!((cache.fetched_at, cache.source), (Some(0), None));
+        assert_eq!(Lyric::from_json_cache(&cache.cache)?.get_lyrics().len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_skips_pinned() -> Result<()> {
+        init_test_database().await;
+        let pinned = seed_cache("[00:00.00]pinned", false).await?;
+        pin(&pinned, true).await?;
+        let same_set = OsuSongInfo {
+            sid: pinned.sid,
+            ..unique_song("Clair de lune")
+        };
+        let lyric = Lyric::parse("[00:00.00]auto", None, None)?;
+        LyricService::save_lyric(&same_set, &lyric, None, false).await?;
+
+        // 批量删除跳过固定的, 除非强制
+        let mut key = CacheKey {
+            sid: Some(pinned.sid as u32),
+            ..Default::default()
+        };
+        assert_eq!(LyricService::delete_cache(&key).await?, 1);
+        assert!(find_cache(&pinned).await?.pinned);
+        key.force = true;
+        assert_eq!(LyricService::delete_cache(&key).await?, 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_manual_cache_pinned() -> Result<()> {
+        init_test_database().await;
+        let manual = seed_cache("[00:00.00]manual", true).await?;
+        assert!(find_cache(&manual).await?.pinned);
+        Ok(())
+    }
+
+    /// 两个谱面集的谱面, 音频相同
+    fn same_audio_songs() -> (OsuSongInfo, OsuSongInfo) {
+        let first = unique_song("Clair de lune");
+        let audio_hash = Some(format!("fixture-audio-{}", first.bid));
+        let other = OsuSongInfo {
+            audio_hash: audio_hash.clone(),
+            ..unique_song("Clair de lune (Cut Ver.)")
+        };
+        (
+            OsuSongInfo {
+                audio_hash,
+                ..first
+            },
+            other,
+        )
+    }
+
+    #[tokio::test]
+    async fn test_shared_lyric_by_audio_hash() -> Result<()> {
+        let t = TestService::start().await;
+        let (first, other) = same_audio_songs();
+
+        LyricService::song_change(t.service, first).await?;
+        assert!(t.service.lock().await.get_now_all_lyrics().is_some());
+        // 另一个谱面集的相同音频直接使用, 不再搜索也不再单独存一份
+        let requests = t.server.requests().len();
+        LyricService::song_change(t.service, other.clone()).await?;
+        assert!(t.service.lock().await.get_now_all_lyrics().is_some());
+        assert_eq!(t.server.requests().len(), requests);
+        assert!(
+            LyricCacheEntity::find_by_bid(other.bid as i32)
+                .await?
+                .is_none()
+        );
+        let linked = BeatmapLyricEntity::find_lyric_ids(Some(other.bid as i32), None).await?;
+        assert_eq!(linked.len(), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shared_manual_lyric_preferred() -> Result<()> {
+        let t = TestService::start().await;
+        let (first, other) = same_audio_songs();
+        LyricService::song_change(t.service, first.clone()).await?;
+
+        // 在一个谱面集手动选择, 其他谱面集也使用
+        LyricService::song_change(t.service, other).await?;
+        let key = SongInfoKey {
+            source_type: "QQ".to_string(),
+            key: "004EoTbn1HPo3n".to_string(),
+        };
+        LyricService::set_song_by_key(t.service, &key).await?;
+        LyricService::song_change(t.service, first.clone()).await?;
+        let info = t.service.lock().await.get_lyric_info().await?;
+        let info = info.ok_or("no lyric info")?;
+        assert_eq!(info.bid, first.bid as u32);
+        assert_eq!(info.source_key.as_deref(), Some("004EoTbn1HPo3n"));
+        assert!(info.manual);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_delete_cache_keeps_shared_lyric() -> Result<()> {
+        let t = TestService::start().await;
+        let (first, other) = same_audio_songs();
+        LyricService::song_change(t.service, first.clone()).await?;
+        LyricService::song_change(t.service, other.clone()).await?;
+        let audio_hash = first.audio_hash.as_deref().ok_or("no audio hash")?;
+
+        // 另一个谱面集还在使用, 只取消这张谱面的使用
+        let key = CacheKey {
+            bid: Some(first.bid as u32),
+            ..Default::default()
+        };
+        LyricService::delete_cache(&key).await?;
+        assert!(SharedLyricEntity::find_by_hash(audio_hash).await?.is_some());
+        let linked =
+            |song: &OsuSongInfo| BeatmapLyricEntity::find_lyric_ids(Some(song.bid as i32), None);
+        assert!(linked(&first).await?.is_empty());
+        assert_eq!(linked(&other).await?.len(), 1);
+
+        // 没有谱面使用后删除
+        let key = CacheKey {
+            bid: Some(other.bid as u32),
+            ..Default::default()
+        };
+        LyricService::delete_cache(&key).await?;
+        assert!(SharedLyricEntity::find_by_hash(audio_hash).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_shared_only_cache_listed_and_deleted() -> Result<()> {
+        let t = TestService::start().await;
+        let (first, other) = same_audio_songs();
+        LyricService::song_change(t.service, first.clone()).await?;
+        LyricService::song_change(t.service, other.clone()).await?;
+
+        // 只通过共享歌词显示的谱面也能列出和查看
+        let query = CacheQuery {
+            bid: Some(other.bid as u32),
+            ..Default::default()
+        };
+        let result = LyricService::find_cache(&query).await?;
+        assert_eq!((result.total, result.items.len()), (1, 1));
+        assert_eq!(result.items[0].title, other.title);
+        assert!(
+            !LyricService::get_cache_lyric(other.bid as u32)
+                .await?
+                .is_empty()
+        );
+
+        let key = CacheKey {
+            bid: Some(other.bid as u32),
+            ..Default::default()
+        };
+        assert_eq!(LyricService::delete_cache(&key).await?, 1);
+        assert_eq!(LyricService::find_cache(&query).await?.total, 0);
+        assert!(
+            LyricService::get_cache_lyric(other.bid as u32)
+                .await
+                .is_err()
+        );
+
+        // 另一个谱面集不受影响, 删除的谱面不再按音频使用, 重新搜索
+        let requests = t.server.requests().len();
+        LyricService::song_change(t.service, first).await?;
+        assert!(t.service.lock().await.get_now_all_lyrics().is_some());
+        assert_eq!(t.server.requests().len(), requests);
+        LyricService::song_change(t.service, other).await?;
+        assert!(t.server.requests().len() > requests);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_pin_shared_only_cache() -> Result<()> {
+        let t = TestService::start().await;
+        let (first, other) = same_audio_songs();
+        LyricService::song_change(t.service, first.clone()).await?;
+        LyricService::song_change(t.service, other.clone()).await?;
+        let audio_hash = first.audio_hash.as_deref().ok_or("no audio hash")?;
+        let pinned = || async {
+            let shared = SharedLyricEntity::find_by_hash(audio_hash).await?;
+            Ok::<_, crate::error::Error>(shared.ok_or("shared lyric lost")?.pinned)
+        };
+
+        // 没有自己的缓存时固定共享歌词
+        pin(&other, true).await?;
+        assert!(pinned().await?);
+        let key = CacheKey {
+            bid: Some(other.bid as u32),
+            ..Default::default()
+        };
+        assert_eq!(LyricService::delete_cache(&key).await?, 0);
+        assert!(
+            !LyricService::get_cache_lyric(other.bid as u32)
+                .await?
+                .is_empty()
+        );
+
+        pin(&other, false).await?;
+        assert!(!pinned().await?);
+        assert!(pin(&unique_song("Clair de lune"), true).await.is_err());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_unblock_adds_exception() -> Result<()> {
+        let t = TestService::start().await;
+        let song = unique_song("Clair de lune");
+        let artist = format!("Fixture Artist {}", song.bid);
+        let song = OsuSongInfo {
+            artist: artist.clone(),
+            artist_unicode: artist.clone(),
+            ..song
+        };
+        let rule = BlockRuleRequest {
+            scope: BlockScope::Artist,
+            value: artist.clone(),
+            block: true,
+        };
+        block_service::add_rule(rule).await?;
+
+        LyricService::song_change(t.service, song.clone()).await?;
+        assert!(t.service.lock().await.get_now_all_lyrics().is_none());
+        assert!(t.server.requests().is_empty());
+
+        // 只取消这一张谱面, 艺术家规则仍然保留
+        LyricService::set_block(t.service, false).await?;
+        assert!(t.service.lock().await.get_now_all_lyrics().is_some());
+        let other = OsuSongInfo {
+            artist,
+            ..unique_song("Clair de lune")
+        };
+        assert!(block_service::is_blocked(&other).await?);
+
+        // 再次拉黑时去掉例外
+        LyricService::set_block(t.service, true).await?;
+        assert!(block_service::is_blocked(&song).await?);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_block_by_bid() -> Result<()> {
+        let t = TestService::start().await;
+        let current = unique_song("Clair de lune");
+        LyricService::song_change(t.service, current.clone()).await?;
+        let other = unique_song("Clair de lune");
+        LyricService::cache_song(&t.sources, &other).await?;
+
+        // 不是当前歌曲, 当前歌词不受影响
+        LyricService::set_block_by_bid(t.service, other.bid as u32, true).await?;
+        assert!(block_service::is_blocked(&other).await?);
+        assert!(!block_service::is_blocked(&current).await?);
+        assert!(t.service.lock().await.get_now_all_lyrics().is_some());
+        LyricService::set_block_by_bid(t.service, other.bid as u32, false).await?;
+        assert!(!block_service::is_blocked(&other).await?);
+
+        // 当前歌曲走原来的流程
+        LyricService::set_block_by_bid(t.service, current.bid as u32, true).await?;
+        assert!(t.service.lock().await.get_now_all_lyrics().is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_offset_by_bid() -> Result<()> {
+        let t = TestService::start().await;
+        // 标题不和其它测试共用, 避免按标题查到别的偏移
+        let current = unique_song("Offset by bid");
+        LyricService::song_change(t.service, current.clone()).await?;
+        let other = unique_song("Offset by bid other");
+        let (bid, sid) = (other.bid as i32, other.sid as i32);
+        LyricService::save_lyric(
+            &other,
+            &Lyric::parse("[00:00.00]line", None, None)?,
+            None,
+            false,
+        )
+        .await?;
+
+        // 不是当前歌曲, 当前偏移不变
+        LyricService::set_offset_by_bid(t.service, bid as u32, 250).await?;
+        assert_eq!(t.service.lock().await.get_offset(), 0);
+        // 其它测试也会写入偏移, 翻页找到这一条
+        let mut item = None;
+        for page in 1.. {
+            let query = OffsetQuery {
+                page,
+                limit: MAX_LIST_PAGE_SIZE,
+            };
+            let list = LyricService::get_offset_list(&query).await?;
+            item = list.items.into_iter().find(|item| item.bid == bid as u32);
+            if item.is_some() || u64::from(page * MAX_LIST_PAGE_SIZE) >= list.total {
+                break;
+            }
+        }
+        let item = item.ok_or("offset not listed")?;
+        assert_eq!((item.sid, item.offset), (sid as u32, 250));
+
+        // 拉黑再取消后只保留偏移, 偏移归零后删除设置
+        LyricService::set_block_by_bid(t.service, bid as u32, true).await?;
+        LyricService::set_block_by_bid(t.service, bid as u32, false).await?;
+        assert_eq!(LyricConfigEntity::find_offset(bid, sid, "").await?, 250);
+        LyricService::set_offset_by_bid(t.service, bid as u32, 0).await?;
+        assert!(LyricConfigEntity::find_by_bid(bid).await?.is_none());
+
+        // 当前歌曲走原来的流程
+        LyricService::set_offset_by_bid(t.service, current.bid as u32, -100).await?;
+        assert_eq!(t.service.lock().await.get_offset(), -100);
+        t.service.lock().await.set_offset(0).await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_offset_unknown_bid() -> Result<()> {
+        let t = TestService::start().await;
+        let unknown = unique_song("Unknown beatmap").bid as u32;
+        assert!(
+            LyricService::set_offset_by_bid(t.service, unknown, 300)
+                .await
+                .is_err()
+        );
+        assert!(
+            LyricService::set_block_by_bid(t.service, unknown, true)
+                .await
+                .is_err()
+        );
+        assert!(
+            LyricConfigEntity::find_by_bid(unknown as i32)
+                .await?
+                .is_none()
+        );
+
+        // 旧版本留下的只有 bid 的设置不影响没有 sid 的谱面
+        let orphan = unique_song("Orphan offset").bid as i32;
+        LyricConfigEntity::save_setting(orphan, 0, "", false, 300).await?;
+        let other = unique_song("Unsubmitted").bid as i32;
+        assert_eq!(LyricConfigEntity::find_offset(other, 0, "").await?, 0);
+        LyricConfigEntity::delete_by_bid(orphan).await
+    }
+
+    #[tokio::test]
+    async fn test_assign_lyric() -> Result<()> {
+        let t = TestService::start().await;
+        let current = unique_song("Clair de lune");
+        LyricService::song_change(t.service, current.clone()).await?;
+        let other = unique_song("Clair de lune");
+
+        // 没有缓存过的谱面需要 sid
+        let mut request = AssignLyricRequest {
+            bid: other.bid as u32,
+            text: Some("[00:01.00]first\n[00:02.00]second".to_string()),
+            trans_text: Some("[00:01.00]一\n[00:02.00]二".to_string()),
+            ..Default::default()
+        };
+        assert!(
+            LyricService::assign_lyric(t.service, &request)
+                .await
+                .is_err()
+        );
+        request.sid = Some(other.sid as u32);
+        request.title = Some("Rêverie".to_string());
+        LyricService::assign_lyric(t.service, &request).await?;
+        let cache = find_cache(&other).await?;
+        assert_eq!(
+            (cache.sid, cache.title.as_str()),
+            (other.sid as i32, "Rêverie")
+        );
+        assert!(cache.manual);
+        let lines = LyricService::get_cache_lyric(other.bid as u32).await?;
+        assert_eq!(lines.len(), 2);
+        // 不是当前歌曲, 当前歌词不变
+        let now = t.service.lock().await.get_now_all_lyrics().map(<[_]>::len);
+        assert_ne!(now, Some(2));
+
+        // 当前歌曲同时替换当前歌词
+        request.bid = current.bid as u32;
+        request.sid = None;
+        LyricService::assign_lyric(t.service, &request).await?;
+        let now = t.service.lock().await.get_now_all_lyrics().map(<[_]>::len);
+        assert_eq!(now, Some(2));
+        let info = t.service.lock().await.get_lyric_info().await?;
+        let info = info.ok_or("no lyric info")?;
+        assert_eq!(info.bid, current.bid as u32);
+        assert!(info.manual && info.source.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_assign_lyric_pushed() -> Result<()> {
+        let t = TestService::start().await;
+        let song = unique_song("Clair de lune");
+        LyricService::song_change(t.service, song.clone()).await?;
+        t.service.lock().await.time_next(1_500).await?;
+        let mut overlay = ALL_SESSIONS.add_test_client().await;
+
+        let line = format!("assigned {}", song.bid);
+        let request = AssignLyricRequest {
+            bid: song.bid as u32,
+            text: Some(format!("[00:01.00]{line}\n[00:02.00]second")),
+            ..Default::default()
+        };
+        LyricService::assign_lyric(t.service, &request).await?;
+        // 其它测试的推送也会收到, 只找这一条
+        let mut pushed = false;
+        while let Ok(message) = overlay.try_recv() {
+            pushed |= message.as_str().is_ok_and(|text| text.contains(&line));
+        }
+        assert!(pushed, "歌词页面没有收到新歌词");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_edit_current_lyric() -> Result<()> {
+        let t = TestService::start().await;
+        let mut this = t.service.lock().await;
+        assert!(this.edit_lyric(|_| Ok(())).await.is_err());
+        drop(this);
+
+        let song = unique_song("Clair de lune");
+        LyricService::song_change(t.service, song.clone()).await?;
+        let mut this = t.service.lock().await;
+        this.time_next(13_000).await?;
+        let edited = Some("edited".to_string());
+        let lines = this
+            .edit_lyric(|lyric| lyric.update_line(1, None, edited, None))
+            .await?;
+        assert_eq!(lines[1].origin.as_deref(), Some("edited"));
+        // 编辑失败时不修改
+        let before = this.get_now_all_lyrics().map(<[_]>::len);
+        assert!(
+            this.edit_lyric(|lyric| lyric.remove_lines(99, 1))
+                .await
+                .is_err()
+        );
+        assert_eq!(this.get_now_all_lyrics().map(<[_]>::len), before);
+        drop(this);
+
+        // 存为固定的手动缓存
+        let cache = find_cache(&song).await?;
+        assert!(cache.manual && cache.pinned);
+        assert_eq!(cache.source.as_deref(), Some("QQ"));
+        let lines = LyricService::get_cache_lyric(song.bid as u32).await?;
+        assert_eq!(lines[1].origin.as_deref(), Some("edited"));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_tap_sync() -> Result<()> {
+        let t = TestService::start().await;
+        let song = unique_song("Clair de lune");
+        LyricService::song_change(t.service, song.clone()).await?;
+
+        let mut this = t.service.lock().await;
+        assert!(this.tap_sync().is_err());
+        let request = TapSyncRequest {
+            text: "第一行\n第二行\n第三行".to_string(),
+            trans_text: None,
+        };
+        this.start_tap_sync(&request)?;
+        // 同名谱面可能设置过偏移
+        this.set_offset(0).await;
+        this.time_next(1_000).await?;
+        this.tap_sync()?;
+        // 点击的时间加上偏移
+        this.set_offset(200).await;
+        this.time_next(5_000).await?;
+        let status = this.tap_sync()?;
+        assert_eq!((status.bid, status.total), (song.bid as u32, 3));
+        assert_eq!(status.next.as_deref(), Some("第三行"));
+
+        // 只保存打过轴的行, 并替换当前歌词
+        let lines = this.save_tap_sync().await?;
+        assert_eq!(lines.len(), 2);
+        assert_eq!((lines[0].time, lines[1].time), (1.0, 5.2));
+        assert_eq!(this.get_now_all_lyrics().map(<[_]>::len), Some(2));
+        assert!(this.get_tap_sync().is_none());
+        this.set_offset(0).await;
+        drop(this);
+
+        let cache = find_cache(&song).await?;
+        assert!(cache.manual && cache.source.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lyric_info_provenance() -> Result<()> {
+        let t = TestService::start().await;
+        let song = unique_song("Clair de lune");
+
+        LyricService::song_change(t.service, song.clone()).await?;
+        let info = t.service.lock().await.get_lyric_info().await?;
+        let info = info.ok_or("no lyric info")?;
+        assert_eq!(info.bid, song.bid as u32);
+        assert_eq!(info.source.as_deref(), Some("QQ"));
+        assert!(info.source_key.is_some());
+        assert!(info.fetched_at.is_some());
+        assert!(!info.manual);
+
+        // 控制端手动选择另一首
+        let key = SongInfoKey {
+            source_type: "QQ".to_string(),
+            key: "004EoTbn1HPo3n".to_string(),
+        };
+        LyricService::set_song_by_key(t.service, &key).await?;
+        let info = t.service.lock().await.get_lyric_info().await?;
+        let info = info.ok_or("no lyric info")?;
+        assert_eq!(info.source_key.as_deref(), Some("004EoTbn1HPo3n"));
+        assert!(info.manual);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lyric_flags_fetched_once() -> Result<()> {
+        let t = TestService::start().await;
+        LyricService::song_change(t.service, unique_song("Clair de lune")).await?;
+        let lyric_requests = || {
+            t.server
+                .requests()
+                .iter()
+                .filter(|request| request.contains(QQ_LYRIC_PATH))
+                .count()
+        };
+
+        let (cache, sources) = {
+            let this = t.service.lock().await;
+            (this.music_cache(), this.sources())
+        };
+        // 选中的歌曲在搜索时已经获取过歌词
+        let chosen = lyric_requests();
+        let known = |cache: &HashMap<&'static str, Vec<SongInfo>>| {
+            cache["QQ"]
+                .iter()
+                .filter(|song| song.is_lyric_flags_known())
+                .count()
+        };
+        assert_eq!(known(&*cache.lock().await), 1);
+
+        // getLyricList 在后台补充, 这里直接等它完成
+        LyricService::fill_lyric_flags(Arc::clone(&cache), sources.clone()).await;
+        assert_eq!(known(&*cache.lock().await), 2);
+        assert_eq!(lyric_requests(), chosen + 1);
+
+        // 已经获取过的不再请求
+        LyricService::fill_lyric_flags(cache, sources).await;
+        assert_eq!(lyric_requests(), chosen + 1);
+        Ok(())
+    }
+
+    /// 每 10 秒一行的歌词, 存为手动选择的缓存
+    async fn seed_timed_song(title: &str) -> Result<OsuSongInfo> {
+        let song = unique_song(title);
+        let text = (0..7)
+            .map(|i| format!("[00:{:02}.00]line {i}\n", i * 10))
+            .collect::<String>();
+        LyricService::save_lyric(&song, &Lyric::parse(&text, None, None)?, None, true).await?;
+        Ok(song)
+    }
+
+    #[tokio::test]
+    async fn test_lyric_sync_anchors() -> Result<()> {
+        let t = TestService::start().await;
+        // 标题不和其它测试共用, 避免按标题查到别的偏移
+        let song = seed_timed_song("Time warp").await?;
+        let (bid, sid) = (song.bid as i32, song.sid as i32);
+        LyricService::song_change(t.service, song).await?;
+
+        let mut this = t.service.lock().await;
+        // 只有一个同步点时只修改偏移
+        this.time_next(10_500).await?;
+        let sync = this.set_sync_anchor(1).await?;
+        assert_eq!((sync.offset, sync.scale), (-500, 1.0));
+
+        // 两个同步点都要对上
+        this.time_next(50_250).await?;
+        let sync = this.set_sync_anchor(5).await?;
+        assert!(sync.scale > 1.0);
+        assert!((this.lyric_time(10_500) - 10_000).abs() <= 1);
+        assert!((this.lyric_time(50_250) - 50_000).abs() <= 1);
+        let timing = LyricConfigEntity::find_timing(bid, sid, "Time warp").await?;
+        assert_eq!(timing, (sync.offset, sync.scale));
+
+        // 离上一个同步点太近时保留倍率
+        this.time_next(52_000).await?;
+        let close = this.set_sync_anchor(5).await?;
+        assert_eq!(close.scale, sync.scale);
+        assert!(this.set_lyric_scale(2.0).await.is_err());
+        this.time_next(100_000).await?;
+        assert!(this.set_sync_anchor(0).await.is_err());
+
+        // 清除后倍率为 1, 偏移也归零时删除设置
+        let cleared = this.clear_lyric_sync().await?;
+        assert_eq!((cleared.offset, cleared.scale), (close.offset, 1.0));
+        assert!(cleared.anchor.is_none());
+        this.set_offset(0).await;
+        assert!(LyricConfigEntity::find_by_bid(bid).await?.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_lyric_scale_by_bid() -> Result<()> {
+        let t = TestService::start().await;
+        // 同一首歌的两个难度, 只有第一个设置了同步点
+        let title = "Time warp difficulties";
+        let first = seed_timed_song(title).await?;
+        let other = seed_timed_song(title).await?;
+        LyricService::song_change(t.service, first).await?;
+        let mut this = t.service.lock().await;
+        this.time_next(10_500).await?;
+        this.set_sync_anchor(1).await?;
+        this.time_next(50_250).await?;
+        let sync = this.set_sync_anchor(5).await?;
+        assert!(sync.scale > 1.0);
+        drop(this);
+
+        // 偏移仍按标题共用, 倍率和同步点不共用
+        LyricService::song_change(t.service, other).await?;
+        let other = t.service.lock().await.get_lyric_sync().await?;
+        assert_eq!((other.offset, other.scale), (sync.offset, 1.0));
+        assert!(other.anchor.is_none());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_combined_offsets() -> Result<()> {
+        let t = TestService::start().await;
+        let source = t.sources[0].name().to_string();
+        let song = unique_song("Offset layers");
+        let origin = LyricOrigin {
+            lyric: SongInfoKey {
+                source_type: source.clone(),
+                key: song.bid.to_string(),
+            },
+            trans: None,
+        };
+        let lyric = Lyric::parse("[00:00.00]first\n[00:10.00]second", None, None)?;
+        LyricService::save_lyric(&song, &lyric, Some(&origin), true).await?;
+        let bid = song.bid as i32;
+        LyricService::song_change(t.service, song).await?;
+
+        let mut this = t.service.lock().await;
+        this.set_global_offset(100).await?;
+        let request = SourceOffsetRequest {
+            source: source.clone(),
+            offset: 50,
+        };
+        this.set_source_offset(&request).await?;
+        // 偏移设置存在共享的 offsetSetting 里, 读回后马上还原, 断言失败也不会留给其它测试
+        let mut loaded = LyricService::with_sources(vec![]);
+        loaded.load_offset_setting().await?;
+        let saved = loaded.get_offset_setting().clone();
+        SettingEntity::save_config(
+            OFFSET_SETTING_KEY.to_string(),
+            serde_json::to_string(&OffsetSetting::default())?,
+        )
+        .await?;
+        assert_eq!(saved.global, 100);
+        assert_eq!(saved.sources.get(&source), Some(&50));
+
+        let unknown = SourceOffsetRequest {
+            source: "unknown".to_string(),
+            offset: 50,
+        };
+        assert!(this.set_source_offset(&unknown).await.is_err());
+        this.set_offset(-30).await;
+
+        // 从缓存加载时也能知道歌词源
+        let offset = this.get_lyric_offset();
+        assert_eq!(offset.source.as_deref(), Some(source.as_str()));
+        assert_eq!(
+            (
+                offset.total,
+                offset.global,
+                offset.source_offset,
+                offset.beatmap
+            ),
+            (120, 100, 50, -30)
+        );
+        assert_eq!(this.lyric_time(1_000), 1_120);
+
+        // 同步点只调整谱面的偏移
+        this.time_next(10_500).await?;
+        let sync = this.set_sync_anchor(1).await?;
+        assert_eq!(sync.offset, -650);
+        assert_eq!(this.lyric_time(10_500), 10_000);
+
+        this.set_global_offset(0).await?;
+        let request = SourceOffsetRequest {
+            offset: 0,
+            ..request
+        };
+        assert!(this.set_source_offset(&request).await?.sources.is_empty());
+        this.clear_lyric_sync().await?;
+        this.set_offset(0).await;
+        assert!(LyricConfigEntity::find_by_bid(bid).await?.is_none());
+        Ok(())
+    }
+}
@@ -1,14 +1,26 @@
+mod block_service;
+#[cfg(test)]
+pub(crate) mod fixture;
 mod lyric_service;
+mod precache_service;
+mod prefetch_service;
+mod search_override_service;
 mod song_source_service;
 mod websocket_service;
 
 use crate::error::Result;
 
 pub use lyric_service::*;
+pub use precache_service::{PRECACHE_SERVICE, PrecacheService};
+pub use prefetch_service::{PREFETCH_SERVICE, PrefetchPolicy, PrefetchService, is_start_play};
 pub use song_source_service::on_osu_state_change;
 pub use websocket_service::on_setting;
 
 pub async fn init_service() -> Result<()> {
+    PREFETCH_SERVICE.load_setting().await?;
+    LyricService::load_miss_retry().await?;
+    LyricService::load_cache_ttl().await?;
+    LYRIC_SERVICE.lock().await.load_offset_setting().await?;
     song_source_service::init_song_service().await?;
     Ok(())
 }
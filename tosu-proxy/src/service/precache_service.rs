@@ -0,0 +1,321 @@
+//! 批量预缓存整个 Songs 目录的歌词
+//!
+//! 每个谱面集只处理一次, 走和正常切歌一样的搜索流程, 结果写入歌词缓存。
+//! 只有真正请求了歌词源才会等待 `interval`, 有歌词源熔断时暂停, 避免被限流
+
+use super::LyricService;
+use super::lyric_service::CacheOutcome;
+use crate::error::Result;
+use crate::lyric::LyricSourceEnum;
+use crate::model::websocket::WebSocketMessage;
+use crate::model::websocket::setting::SettingPayload;
+use crate::model::websocket::setting::precache::{
+    PrecacheFailure, PrecacheRequest, PrecacheState, PrecacheStatus,
+};
+use crate::osu_source::{OsuSetEntry, scan_songs_folder, songs_folder};
+use crate::server::ALL_SESSIONS;
+use crate::util::{hash_audio, read_audio_length};
+use std::path::PathBuf;
+use std::sync::{LazyLock, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+use tokio::time::sleep;
+use tracing::{debug, info, warn};
+
+const DEFAULT_INTERVAL: u64 = 3000;
+/// 保留的失败记录数
+const MAX_FAILURES: usize = 50;
+/// 歌词源熔断时重新检查的间隔
+const CIRCUIT_RETRY: Duration = Duration::from_secs(5);
+
+pub static PRECACHE_SERVICE: LazyLock<PrecacheService> =
+    LazyLock::new(|| PrecacheService::new(LyricSourceEnum::all()));
+
+pub struct PrecacheService {
+    sources: Vec<LyricSourceEnum>,
+    status: Mutex<PrecacheStatus>,
+    paused: watch::Sender<bool>,
+    task: Mutex<Option<AbortHandle>>,
+}
+
+impl PrecacheService {
+    pub fn new(sources: Vec<LyricSourceEnum>) -> Self {
+        Self {
+            sources,
+            status: Mutex::new(PrecacheStatus::default()),
+            paused: watch::Sender::new(false),
+            task: Mutex::new(None),
+        }
+    }
+
+    pub fn status(&self) -> PrecacheStatus {
+        self.status.lock().unwrap().clone()
+    }
+
+    fn is_running(&self) -> bool {
+        matches!(
+            self.status.lock().unwrap().state,
+            PrecacheState::Scanning | PrecacheState::Running | PrecacheState::Paused
+        )
+    }
+
+    pub async fn start(&'static self, request: PrecacheRequest) -> Result<()> {
+        {
+            // 检查和保存任务在同一个锁内, 同时到来的请求只会启动一次
+            let mut task = self.task.lock().unwrap();
+            if self.is_running() {
+                return Err("预缓存正在进行中".into());
+            }
+            let songs = match request.path {
+                Some(path) => PathBuf::from(path),
+                None => songs_folder().ok_or("未获取到 Songs 目录, 请先连接 tosu 或指定目录")?,
+            };
+            let interval = Duration::from_millis(request.interval.unwrap_or(DEFAULT_INTERVAL));
+
+            *self.status.lock().unwrap() = PrecacheStatus {
+                state: PrecacheState::Scanning,
+                ..Default::default()
+            };
+            self.paused.send_replace(false);
+            info!("开始预缓存 {}", songs.display());
+            task.replace(tokio::spawn(self.run(songs, interval)).abort_handle());
+        }
+        self.notify().await;
+        Ok(())
+    }
+
+    pub async fn pause(&self) -> Result<()> {
+        if !self.is_running() {
+            return Err("预缓存没有在运行".into());
+        }
+        self.paused.send_replace(true);
+        self.update(|status| status.state = PrecacheState::Paused)
+            .await;
+        Ok(())
+    }
+
+    pub async fn resume(&self) -> Result<()> {
+        if !self.is_running() {
+            return Err("预缓存没有在运行".into());
+        }
+        self.paused.send_replace(false);
+        self.update(|status| status.state = PrecacheState::Running)
+            .await;
+        Ok(())
+    }
+
+    pub async fn cancel(&self) -> Result<()> {
+        if !self.is_running() {
+            return Err("预缓存没有在运行".into());
+        }
+        if let Some(task) = self.task.lock().unwrap().take() {
+            task.abort();
+        }
+        self.update(|status| {
+            status.state = PrecacheState::Cancelled;
+            status.current = None;
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn run(&'static self, songs: PathBuf, interval: Duration) {
+        let entries = tokio::task::spawn_blocking(move || scan_songs_folder(&songs)).await;
+        let entries = match entries {
+            Ok(Ok(entries)) => entries,
+            Ok(Err(err)) => return self.fail(err.to_string()).await,
+            Err(err) => return self.fail(err.to_string()).await,
+        };
+        self.update(|status| {
+            status.total = entries.len();
+            if status.state == PrecacheState::Scanning {
+                status.state = PrecacheState::Running;
+            }
+        })
+        .await;
+
+        for entry in entries {
+            self.wait_ready().await;
+            let title = entry.meta.title_unicode.clone();
+            self.update(|status| status.current = Some(title)).await;
+
+            let searched = self.process(entry).await;
+            if searched && !interval.is_zero() {
+                sleep(interval).await;
+            }
+        }
+
+        info!("预缓存完成");
+        self.task.lock().unwrap().take();
+        self.update(|status| {
+            status.state = PrecacheState::Finished;
+            status.current = None;
+        })
+        .await;
+    }
+
+    /// 暂停或歌词源熔断时等待
+    async fn wait_ready(&self) {
+        loop {
+            let mut paused = self.paused.subscribe();
+            let _ = paused.wait_for(|paused| !paused).await;
+            if !LyricService::any_circuit_open(&self.sources) {
+                return;
+            }
+            debug!("歌词源熔断中, 暂停预缓存");
+            sleep(CIRCUIT_RETRY).await;
+        }
+    }
+
+    /// 处理一个谱面集, 返回是否请求了歌词源
+    async fn process(&self, entry: OsuSetEntry) -> bool {
+        let OsuSetEntry { meta, audio_path } = entry;
+        let mut failure = PrecacheFailure {
+            bid: meta.bid,
+            sid: meta.sid,
+            title: meta.title_unicode.clone(),
+            error: String::new(),
+        };
+        // 缓存按 bid 保存, 没有 bid 的旧谱面无法在游戏中对应
+        if meta.bid <= 0 {
+            failure.error = "缺少谱面 ID".to_string();
+            self.record(Err(failure)).await;
+            return false;
+        }
+
+        let length = read_audio_length(&audio_path).await.unwrap_or(-1);
+        let audio_hash = hash_audio(&audio_path).await.ok();
+        let song = meta.into_song(length, audio_hash);
+        let outcome = match LyricService::cache_song(&self.sources, &song).await {
+            Ok(CacheOutcome::Failed) => Err("歌词源请求失败".to_string()),
+            Ok(outcome) => Ok(outcome),
+            Err(err) => {
+                warn!("预缓存 {} 失败: {}", failure.title, err);
+                Err(err.to_string())
+            }
+        };
+        let searched = !matches!(
+            outcome,
+            Ok(CacheOutcome::Blocked | CacheOutcome::Cached | CacheOutcome::Missed)
+        );
+        self.record(outcome.map_err(|error| PrecacheFailure { error, ..failure }))
+            .await;
+        searched
+    }
+
+    /// `outcome` 不会是 [`CacheOutcome::Failed`], 请求失败时传入 `Err`
+    async fn record(&self, outcome: std::result::Result<CacheOutcome, PrecacheFailure>) {
+        self.update(|status| {
+            status.done += 1;
+            match outcome {
+                Ok(CacheOutcome::Saved(..)) => status.saved += 1,
+                Ok(CacheOutcome::Blocked | CacheOutcome::Cached | CacheOutcome::Missed) => {
+                    status.skipped += 1
+                }
+                Ok(CacheOutcome::NotFound | CacheOutcome::Failed) => status.not_found += 1,
+                Err(failure) => {
+                    status.failed += 1;
+                    if status.failures.len() >= MAX_FAILURES {
+                        status.failures.remove(0);
+                    }
+                    status.failures.push(failure);
+                }
+            }
+        })
+        .await;
+    }
+
+    async fn fail(&self, error: String) {
+        warn!("预缓存失败: {error}");
+        self.task.lock().unwrap().take();
+        self.update(|status| {
+            status.state = PrecacheState::Failed;
+            status.error = Some(error);
+        })
+        .await;
+    }
+
+    async fn update(&self, f: impl FnOnce(&mut PrecacheStatus)) {
+        f(&mut self.status.lock().unwrap());
+        self.notify().await;
+    }
+
+    /// 进度只发给控制端
+    async fn notify(&self) {
+        let mut payload = SettingPayload::new("precacheProgress".to_string());
+        if payload.set_replay(self.status()).is_ok() {
+            ALL_SESSIONS
+                .send_to_all_setter(WebSocketMessage::Setting(payload).into())
+                .await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::{LyricCacheEntity, init_test_database};
+    use crate::lyric::fixture::*;
+    use crate::osu_source::OsuSongInfo;
+    use tokio::time::Instant;
+
+    fn write_set(songs: &std::path::Path, song: &OsuSongInfo) {
+        let (bid, sid, title) = (song.bid, song.sid, &song.title);
+        let folder = songs.join(format!("{sid} Claude Debussy - {title}"));
+        std::fs::create_dir_all(&folder).unwrap();
+        let osu = format!(
+            "osu file format v14\n\n[General]\nAudioFilename: audio.mp3\n\n[Metadata]\n\
+             Title:{title}\nArtist:Claude Debussy\nBeatmapID:{bid}\nBeatmapSetID:{sid}\n"
+        );
+        std::fs::write(folder.join("normal.osu"), osu).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_precache_songs_folder() -> Result<()> {
+        init_test_database().await;
+        let server = FixtureServer::start_all().await;
+        let sources = vec![server.qq_source()];
+        let precache = Box::leak(Box::new(PrecacheService::new(sources.clone())));
+
+        let songs = std::env::temp_dir().join(format!("tosu-proxy-songs-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&songs);
+        let new = unique_song("Clair de lune");
+        // 第二个谱面集已经有缓存
+        let cached = OsuSongInfo {
+            length: -1,
+            ..unique_song("Clair de lune")
+        };
+        write_set(&songs, &new);
+        write_set(&songs, &cached);
+        LyricService::cache_song(&sources, &cached).await?;
+
+        // 同时到来的请求只启动一次
+        let request = PrecacheRequest {
+            path: Some(songs.to_string_lossy().to_string()),
+            interval: Some(0),
+        };
+        let (first, second) =
+            tokio::join!(precache.start(request.clone()), precache.start(request));
+        assert!(first.is_ok() != second.is_ok());
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while precache.status().state != PrecacheState::Finished {
+            assert!(Instant::now() < deadline, "预缓存超时");
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        let status = precache.status();
+        assert_eq!(status.total, 2);
+        assert_eq!(status.done, 2);
+        assert_eq!(status.saved, 1);
+        assert_eq!(status.skipped, 1);
+        assert!(
+            LyricCacheEntity::find_by_bid(new.bid as i32)
+                .await?
+                .is_some()
+        );
+        std::fs::remove_dir_all(&songs)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! 选歌界面的歌词预取
+//!
+//! 只处理最后悬停的谱面: 停留 `dwell` 之后才开始, 两次预取之间至少间隔 `min_interval`,
+//! 预取过程中换了谱面会直接取消, 快速滚动选歌列表时不会对歌词源发出大量请求
+
+use super::lyric_service::CacheOutcome;
+use super::{LYRIC_SERVICE, LyricService};
+use crate::database::SettingEntity;
+use crate::error::Result;
+use crate::lyric::LyricSourceEnum;
+use crate::osu_source::OsuSongInfo;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{LazyLock, OnceLock};
+use std::time::Duration;
+use tokio::sync::{Mutex, watch};
+use tokio::time::{Instant, sleep, sleep_until};
+use tracing::{debug, warn};
+
+const PREFETCH_SETTING_KEY: &str = "prefetch";
+
+pub static PREFETCH_SERVICE: LazyLock<PrefetchService> = LazyLock::new(|| {
+    PrefetchService::new(
+        &LYRIC_SERVICE,
+        LyricSourceEnum::all(),
+        PrefetchPolicy::default(),
+    )
+});
+
+#[derive(Debug, Clone)]
+pub struct PrefetchPolicy {
+    /// 在同一张谱面停留多久才开始预取
+    pub dwell: Duration,
+    /// 两次预取开始的最小间隔
+    pub min_interval: Duration,
+}
+
+impl Default for PrefetchPolicy {
+    fn default() -> Self {
+        Self {
+            dwell: Duration::from_millis(800),
+            min_interval: Duration::from_secs(2),
+        }
+    }
+}
+
+pub struct PrefetchService {
+    enabled: AtomicBool,
+    service: &'static Mutex<LyricService>,
+    sources: Vec<LyricSourceEnum>,
+    policy: PrefetchPolicy,
+    // 只保留最新的一个, 新的会覆盖旧的
+    pending: watch::Sender<Option<OsuSongInfo>>,
+    worker: OnceLock<()>,
+}
+
+impl PrefetchService {
+    pub fn new(
+        service: &'static Mutex<LyricService>,
+        sources: Vec<LyricSourceEnum>,
+        policy: PrefetchPolicy,
+    ) -> Self {
+        Self {
+            enabled: AtomicBool::new(false),
+            service,
+            sources,
+            policy,
+            pending: watch::Sender::new(None),
+            worker: OnceLock::new(),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::SeqCst)
+    }
+
+    /// 从数据库读取开关
+    pub async fn load_setting(&self) -> Result<()> {
+        let enabled = SettingEntity::get_config(PREFETCH_SETTING_KEY)
+            .await?
+            .and_then(|json| serde_json::from_str(&json).ok())
+            .unwrap_or(false);
+        self.enabled.store(enabled, Ordering::SeqCst);
+        Ok(())
+    }
+
+    pub async fn set_enabled(&self, enabled: bool) -> Result<()> {
+        SettingEntity::save_config(
+            PREFETCH_SETTING_KEY.to_string(),
+            serde_json::to_string(&enabled)?,
+        )
+        .await?;
+        self.enabled.store(enabled, Ordering::SeqCst);
+        if !enabled {
+            self.cancel();
+        }
+        Ok(())
+    }
+
+    /// 预取 `song`, 替换掉还没完成的预取
+    pub fn submit(&'static self, song: OsuSongInfo) {
+        self.worker.get_or_init(|| {
+            tokio::spawn(self.run(self.pending.subscribe()));
+        });
+        self.pending.send_replace(Some(song));
+    }
+
+    /// 取消还没完成的预取
+    pub fn cancel(&self) {
+        self.pending.send_replace(None);
+    }
+
+    async fn run(&'static self, mut pending: watch::Receiver<Option<OsuSongInfo>>) {
+        let mut next_start = Instant::now();
+        loop {
+            if pending.changed().await.is_err() {
+                return;
+            }
+
+            // 停留足够久才开始, 期间换了谱面就重新计时
+            loop {
+                tokio::select! {
+                    changed = pending.changed() => if changed.is_err() { return },
+                    _ = sleep(self.policy.dwell) => break,
+                }
+            }
+            sleep_until(next_start).await;
+            let Some(song) = pending.borrow_and_update().clone() else {
+                continue;
+            };
+            next_start = Instant::now() + self.policy.min_interval;
+
+            tokio::select! {
+                _ = pending.changed() => {
+                    debug!("取消预取 {}", song.title_unicode);
+                    // 让外层循环处理新的谱面
+                    pending.mark_changed();
+                }
+                result = self.prefetch(&song) => {
+                    if let Err(err) = result {
+                        warn!("预取 {} 失败: {}", song.title_unicode, err);
+                    }
+                }
+            }
+        }
+    }
+
+    async fn prefetch(&self, song: &OsuSongInfo) -> Result<()> {
+        // 低优先级: 有歌词源熔断时不预取, 把请求留给正常的搜索
+        if LyricService::any_circuit_open(&self.sources) {
+            debug!("歌词源熔断中, 跳过预取 {}", song.title_unicode);
+            return Ok(());
+        }
+
+        let title = song.title_unicode.as_str();
+        debug!("预取 {title}");
+        match LyricService::cache_song(&self.sources, song).await? {
+            CacheOutcome::Saved(lyric, origin) => {
+                LyricService::apply_prefetched(self.service, song, lyric, origin).await;
+            }
+            CacheOutcome::NotFound => debug!("预取没有找到歌词 {title}"),
+            CacheOutcome::Blocked
+            | CacheOutcome::Cached
+            | CacheOutcome::Missed
+            | CacheOutcome::Failed => {}
+        }
+        Ok(())
+    }
+}
+
+/// 离开选歌界面时是否开始游玩, 开始游玩时要再通知一次同一张谱面, 预取没完成时正常搜索
+///
+/// 回到主菜单等其它界面时不算, 否则每次离开选歌界面都会发出预取想省下的请求
+pub fn is_start_play(was_browsing: bool, playing: bool) -> bool {
+    was_browsing && playing
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::LyricCacheEntity;
+    use crate::lyric::fixture;
+    use crate::service::fixture::TestService;
+
+    /// 选歌界面划过的谱面
+    fn browsing(song: OsuSongInfo) -> OsuSongInfo {
+        OsuSongInfo {
+            browsing: true,
+            ..song
+        }
+    }
+
+    fn prefetch(t: &TestService) -> &'static PrefetchService {
+        let policy = PrefetchPolicy {
+            dwell: Duration::from_millis(100),
+            min_interval: Duration::ZERO,
+        };
+        Box::leak(Box::new(PrefetchService::new(
+            t.service,
+            t.sources.clone(),
+            policy,
+        )))
+    }
+
+    #[tokio::test]
+    async fn test_prefetch_latest_wins() -> Result<()> {
+        let t = TestService::start().await;
+        let (service, prefetch) = (t.service, prefetch(&t));
+
+        let skipped = browsing(fixture::unique_song("Skipped song"));
+        let hovered = browsing(fixture::unique_song("Clair de lune"));
+        assert!(!LyricService::song_change_cached(service, skipped.clone()).await?);
+        assert!(!LyricService::song_change_cached(service, hovered.clone()).await?);
+
+        // 快速划过一张谱面, 停在第二张; 暂停时间, 停留多久不受机器负载影响
+        tokio::time::pause();
+        prefetch.submit(skipped.clone());
+        tokio::time::advance(Duration::from_millis(50)).await;
+        prefetch.submit(hovered.clone());
+        tokio::time::advance(Duration::from_millis(100)).await;
+        // 请求歌词源和数据库不受暂停的时间影响
+        tokio::time::resume();
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while LyricCacheEntity::find_by_bid(hovered.bid as i32)
+            .await?
+            .is_none()
+        {
+            assert!(Instant::now() < deadline, "预取超时");
+            sleep(Duration::from_millis(50)).await;
+        }
+
+        assert!(
+            LyricCacheEntity::find_by_bid(skipped.bid as i32)
+                .await?
+                .is_none()
+        );
+        let requests = t.server.requests();
+        assert!(requests.iter().all(|request| !request.contains("Skipped")));
+        // 还停留在这张谱面, 预取的结果直接使用
+        assert!(service.lock().await.get_now_all_lyrics().is_some());
+        Ok(())
+    }
+
+    #[test]
+    fn test_leave_select_to_menu() {
+        // 回到主菜单不搜索
+        assert!(!is_start_play(true, false));
+        // 从选歌界面开始游玩
+        assert!(is_start_play(true, true));
+        // 游玩中和成绩界面之间切换不算
+        assert!(!is_start_play(false, true));
+    }
+}
@@ -0,0 +1,353 @@
+//! 搜索歌词时使用的关键字
+//!
+//! 谱面的罗马音或风格化的元数据常常搜不到歌词, 可以为单张谱面或整个谱面集指定标题/艺术家,
+//! 也可以为艺术家设置全局别名。优先级: bid > 谱面集 > 别名 > 谱面元数据
+
+use crate::database::{
+    ArtistAliasEntity, BeatmapLyricEntity, LyricCacheEntity, LyricMissEntity, SearchOverrideEntity,
+    SearchOverrideModel, SharedLyricEntity,
+};
+use crate::error::Result;
+use crate::model::websocket::setting::search_override::{
+    ArtistAlias, SearchOverride, SearchOverrideRequest,
+};
+use crate::osu_source::OsuSongInfo;
+use tracing::{debug, warn};
+
+/// 返回搜索用的 (标题, 艺术家), 查询失败时使用谱面元数据
+pub async fn search_terms(song: &OsuSongInfo) -> (String, String) {
+    let title = song.title_unicode.to_string();
+    let artist = song.artist_unicode.to_string();
+    match resolve_terms(song).await {
+        Ok((search_title, search_artist)) => {
+            if search_title != title || search_artist != artist {
+                debug!("{title} 使用关键字 {search_title} {search_artist} 搜索");
+            }
+            (search_title, search_artist)
+        }
+        Err(err) => {
+            warn!("查询搜索关键字失败: {}", err);
+            (title, artist)
+        }
+    }
+}
+
+async fn resolve_terms(song: &OsuSongInfo) -> Result<(String, String)> {
+    let custom = SearchOverrideEntity::find_for(song.bid as i32, song.sid as i32).await?;
+    let (title, artist) = match custom {
+        Some(custom) => (custom.title, custom.artist),
+        None => (None, None),
+    };
+    let title = title.unwrap_or_else(|| song.title_unicode.to_string());
+    if let Some(artist) = artist {
+        return Ok((title, artist));
+    }
+    for artist in [&song.artist_unicode, &song.artist] {
+        if let Some(alias) = ArtistAliasEntity::find_alias(artist.trim()).await? {
+            return Ok((title, alias));
+        }
+    }
+    Ok((title, song.artist_unicode.to_string()))
+}
+
+pub async fn get_overrides() -> Result<Vec<SearchOverride>> {
+    Ok(SearchOverrideEntity::find_all()
+        .await?
+        .into_iter()
+        .map(into_override)
+        .collect())
+}
+
+pub async fn set_override(request: SearchOverrideRequest) -> Result<SearchOverride> {
+    let trim = |text: Option<String>| {
+        text.map(|text| text.trim().to_string())
+            .filter(|text| !text.is_empty())
+    };
+    let title = trim(request.title);
+    let artist = trim(request.artist);
+    if title.is_none() && artist.is_none() {
+        return Err("title 和 artist 至少填一个".into());
+    }
+    let (bid, sid) = match (request.bid, request.sid) {
+        (Some(bid), sid) if bid > 0 => (bid as i32, sid.unwrap_or_default() as i32),
+        (_, Some(sid)) if sid > 0 => (0, sid as i32),
+        _ => return Err("需要 bid 或 sid".into()),
+    };
+    let model = SearchOverrideEntity::save(bid, sid, title.as_deref(), artist.as_deref()).await?;
+    // 用旧关键字没找到的记录和自动获取的歌词作废, 下次游玩时用新关键字搜索
+    if bid > 0 {
+        LyricMissEntity::delete_by_bid(bid).await?;
+        // 没有 bid 的记录时会按 sid 查找没找到的记录
+        if sid > 0 {
+            LyricMissEntity::delete_by_sid(sid).await?;
+        }
+        LyricCacheEntity::delete_auto(Some(bid), sid).await?;
+        delete_auto_shared(BeatmapLyricEntity::find_lyric_ids(Some(bid), None).await?).await?;
+    } else {
+        LyricMissEntity::delete_by_sid(sid).await?;
+        LyricCacheEntity::delete_auto(None, sid).await?;
+        delete_auto_shared(BeatmapLyricEntity::find_lyric_ids(None, Some(sid)).await?).await?;
+    }
+    Ok(into_override(model))
+}
+
+/// 删除谱面使用的自动获取的共享歌词和使用它的记录, 否则仍会按音频找到它
+async fn delete_auto_shared(ids: Vec<i32>) -> Result<()> {
+    if ids.is_empty() {
+        return Ok(());
+    }
+    let deleted = SharedLyricEntity::delete_auto(ids).await?;
+    if !deleted.is_empty() {
+        BeatmapLyricEntity::delete_by_lyric_ids(deleted).await?;
+    }
+    Ok(())
+}
+
+pub async fn delete_override(id: i32) -> Result<()> {
+    if !SearchOverrideEntity::delete_override(id).await? {
+        return Err(format!("没有 id 为 {id} 的搜索关键字").into());
+    }
+    Ok(())
+}
+
+pub async fn get_aliases() -> Result<Vec<ArtistAlias>> {
+    Ok(ArtistAliasEntity::find_all()
+        .await?
+        .into_iter()
+        .map(|model| ArtistAlias {
+            artist: model.artist,
+            alias: model.alias,
+        })
+        .collect())
+}
+
+pub async fn set_alias(alias: ArtistAlias) -> Result<()> {
+    let artist = alias.artist.trim();
+    let alias = alias.alias.trim();
+    if artist.is_empty() || alias.is_empty() {
+        return Err("artist 和 alias 不能为空".into());
+    }
+    ArtistAliasEntity::save(artist, alias).await?;
+    LyricMissEntity::delete_by_artist(artist).await?;
+    LyricCacheEntity::delete_auto_by_artist(artist).await?;
+    delete_auto_shared(BeatmapLyricEntity::find_lyric_ids_by_artist(artist).await?).await
+}
+
+pub async fn delete_alias(artist: &str) -> Result<()> {
+    if !ArtistAliasEntity::delete_alias(artist.trim()).await? {
+        return Err(format!("没有 {artist} 的别名").into());
+    }
+    Ok(())
+}
+
+fn into_override(model: SearchOverrideModel) -> SearchOverride {
+    SearchOverride {
+        id: model.id,
+        bid: model.bid as u32,
+        sid: model.sid as u32,
+        title: model.title,
+        artist: model.artist,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::database::init_test_database;
+    use crate::lyric::Lyric;
+    use crate::lyric::fixture::{test_song, unique_song};
+    use crate::service::LyricService;
+    use crate::service::fixture::TestService;
+
+    #[tokio::test]
+    async fn test_search_terms_precedence() -> Result<()> {
+        init_test_database().await;
+        let song = OsuSongInfo {
+            artist: "Kamelia 45".to_string(),
+            artist_unicode: "Kamelia 45".to_string(),
+            ..test_song(45001, 45000, "Clair de lune")
+        };
+        let other = OsuSongInfo {
+            artist: "Kamelia 45".to_string(),
+            artist_unicode: "Kamelia 45".to_string(),
+            ..test_song(45002, 45000, "Clair de lune")
+        };
+        let terms = |title: &str, artist: &str| (title.to_string(), artist.to_string());
+
+        // 别名忽略大小写
+        set_alias(ArtistAlias {
+            artist: "kamelia 45".to_string(),
+            alias: "かめりあ".to_string(),
+        })
+        .await?;
+        assert_eq!(
+            search_terms(&song).await,
+            terms("Clair de lune", "かめりあ")
+        );
+
+        // 谱面集只改标题, 艺术家仍然用别名
+        let set = SearchOverrideRequest {
+            sid: Some(45000),
+            title: Some(" Rêverie ".to_string()),
+            ..Default::default()
+        };
+        set_override(set).await?;
+        assert_eq!(search_terms(&other).await, terms("Rêverie", "かめりあ"));
+
+        // bid 优先于谱面集
+        let beatmap = SearchOverrideRequest {
+            bid: Some(45001),
+            sid: Some(45000),
+            title: Some("Arabesque".to_string()),
+            artist: Some("Debussy".to_string()),
+        };
+        let saved = set_override(beatmap).await?;
+        assert_eq!(search_terms(&song).await, terms("Arabesque", "Debussy"));
+        assert_eq!(search_terms(&other).await, terms("Rêverie", "かめりあ"));
+
+        delete_override(saved.id).await?;
+        assert!(delete_override(saved.id).await.is_err());
+        delete_alias("KAMELIA 45").await?;
+        assert_eq!(search_terms(&song).await, terms("Rêverie", "Kamelia 45"));
+        assert!(
+            set_override(SearchOverrideRequest::default())
+                .await
+                .is_err()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_override_saved_once() -> Result<()> {
+        init_test_database().await;
+        let song = unique_song("Clair de lune");
+        let request = |sid: Option<u32>, title: &str| SearchOverrideRequest {
+            bid: Some(song.bid as u32),
+            sid,
+            title: Some(title.to_string()),
+            ..Default::default()
+        };
+        set_override(request(Some(song.sid as u32), "Arabesque")).await?;
+        // sid 不同或没有 sid 时仍然是同一条
+        set_override(request(None, "Rêverie")).await?;
+        let saved = get_overrides().await?;
+        let saved = saved.iter().filter(|item| item.bid == song.bid as u32);
+        assert_eq!(saved.count(), 1);
+        assert_eq!(search_terms(&song).await.0, "Rêverie");
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_override_clears_previous_result() -> Result<()> {
+        init_test_database().await;
+        let missed = unique_song("Clair de lune");
+        let sibling = OsuSongInfo {
+            sid: missed.sid,
+            ..unique_song("Clair de lune")
+        };
+        let cached = unique_song("Clair de lune");
+        let manual = OsuSongInfo {
+            sid: cached.sid,
+            ..unique_song("Clair de lune")
+        };
+        LyricMissEntity::save(&sibling, false).await?;
+        let lyric = Lyric::parse("[00:00.00]line", None, None)?;
+        LyricCacheEntity::save(&cached, &lyric, None, false).await?;
+        LyricCacheEntity::save(&manual, &lyric, None, true).await?;
+
+        let request = |bid: Option<u32>, sid: u32| SearchOverrideRequest {
+            bid,
+            sid: Some(sid),
+            title: Some("Arabesque".to_string()),
+            ..Default::default()
+        };
+        // 同一谱面集其他难度的记录也会挡住搜索
+        assert!(
+            LyricMissEntity::find_valid(missed.bid as i32, missed.sid as i32, 0)
+                .await?
+                .is_some()
+        );
+        set_override(request(Some(missed.bid as u32), missed.sid as u32)).await?;
+        let miss = LyricMissEntity::find_valid(missed.bid as i32, missed.sid as i32, 0).await?;
+        assert!(miss.is_none());
+
+        // 谱面集的自动获取的歌词作废, 手动选择的保留
+        set_override(request(None, cached.sid as u32)).await?;
+        assert!(
+            LyricCacheEntity::find_by_bid(cached.bid as i32)
+                .await?
+                .is_none()
+        );
+        assert!(
+            LyricCacheEntity::find_by_bid(manual.bid as i32)
+                .await?
+                .is_some()
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_override_searches_again() -> Result<()> {
+        let t = TestService::start().await;
+        let song = unique_song("Clair de lune");
+        let song = OsuSongInfo {
+            audio_hash: Some(format!("fixture-audio-{}", song.bid)),
+            ..song
+        };
+        LyricService::song_change(t.service, song.clone()).await?;
+        LyricService::song_change(t.service, unique_song("Clair de lune")).await?;
+
+        // 谱面自己的缓存和相同音频的歌词都作废
+        let request = SearchOverrideRequest {
+            bid: Some(song.bid as u32),
+            sid: Some(song.sid as u32),
+            title: Some("Clair de lune".to_string()),
+            ..Default::default()
+        };
+        set_override(request).await?;
+        let audio_hash = song.audio_hash.as_deref().ok_or("no audio hash")?;
+        assert!(SharedLyricEntity::find_by_hash(audio_hash).await?.is_none());
+        let requests = t.server.requests().len();
+        LyricService::song_change(t.service, song).await?;
+        assert!(t.server.requests().len() > requests);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_alias_clears_previous_result() -> Result<()> {
+        init_test_database().await;
+        let song = unique_song("Clair de lune");
+        // SQLite 的 lower() 只转换 ASCII, 只有 Unicode 艺术家能匹配
+        let artist = format!("ÉLAN {}", song.bid);
+        let song = OsuSongInfo {
+            artist: format!("Elan {}", song.bid),
+            artist_unicode: artist.clone(),
+            ..song
+        };
+        let other = unique_song("Clair de lune");
+        LyricMissEntity::save(&song, false).await?;
+        LyricMissEntity::save(&other, false).await?;
+        let cached = OsuSongInfo {
+            bid: song.bid + 1,
+            ..song.clone()
+        };
+        let lyric = Lyric::parse("[00:00.00]line", None, None)?;
+        LyricCacheEntity::save(&cached, &lyric, None, false).await?;
+
+        set_alias(ArtistAlias {
+            artist: artist.to_lowercase(),
+            alias: "Debussy".to_string(),
+        })
+        .await?;
+        let miss =
+            |song: &OsuSongInfo| LyricMissEntity::find_valid(song.bid as i32, song.sid as i32, 0);
+        assert!(miss(&song).await?.is_none());
+        assert!(miss(&other).await?.is_some());
+        assert!(
+            LyricCacheEntity::find_by_bid(cached.bid as i32)
+                .await?
+                .is_none()
+        );
+        delete_alias(&artist).await
+    }
+}
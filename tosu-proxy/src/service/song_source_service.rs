@@ -1,4 +1,4 @@
-use super::LYRIC_SERVICE;
+use super::{LYRIC_SERVICE, LyricService, PREFETCH_SERVICE};
 use crate::error::Result;
 use crate::model::websocket::WebSocketMessage;
 use crate::model::websocket::setting::SettingPayload;
@@ -37,7 +37,7 @@ async fn on_time_update(time: i32) {
     }
 }
 
-async fn on_song_update(song: OsuSongInfo) {
+async fn on_song_update(mut song: OsuSongInfo) {
     if song.sid < 0 && song.artist == "nekodex" {
         // 忽略掉首页音乐
         return;
@@ -49,8 +49,18 @@ async fn on_song_update(song: OsuSongInfo) {
 
     let task = tokio::spawn(async move {
         tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
-        let mut lyric_service = LYRIC_SERVICE.lock().await;
-        if let Err(e) = lyric_service.song_change(song).await {
+        song.load_audio_hash().await;
+        // 选歌界面只查缓存, 没有的交给预取
+        if song.browsing && PREFETCH_SERVICE.is_enabled() {
+            match LyricService::song_change_cached(&LYRIC_SERVICE, song.clone()).await {
+                Ok(true) => {}
+                Ok(false) => PREFETCH_SERVICE.submit(song),
+                Err(e) => error!("song update error: {}", e),
+            }
+            return;
+        }
+        PREFETCH_SERVICE.cancel();
+        if let Err(e) = LyricService::song_change(&LYRIC_SERVICE, song).await {
             error!("song update error: {}", e);
         }
     });
@@ -3,9 +3,22 @@ use crate::error::{Error, Result};
 use crate::lyric::SongInfoKey;
 use crate::model::websocket::WebSocketMessage;
 use crate::model::websocket::setting::SettingPayload;
+use crate::model::websocket::setting::assign::AssignLyricRequest;
+use crate::model::websocket::setting::block::BlockRuleRequest;
+use crate::model::websocket::setting::cache::{CacheKey, CachePin, CacheQuery};
+use crate::model::websocket::setting::lyric_edit::{
+    LyricLineEdit, LyricLineInsert, LyricLineRange,
+};
+use crate::model::websocket::setting::offset::{OffsetQuery, OffsetValue, SourceOffsetRequest};
+use crate::model::websocket::setting::precache::PrecacheRequest;
+use crate::model::websocket::setting::preview::LyricPreview;
+use crate::model::websocket::setting::search::SearchLyricRequest;
+use crate::model::websocket::setting::search_override::{ArtistAlias, SearchOverrideRequest};
+use crate::model::websocket::setting::tap_sync::TapSyncRequest;
 use crate::osu_source::OsuState;
 use crate::server::ALL_SESSIONS;
-use crate::service::LYRIC_SERVICE;
+use crate::service::{LYRIC_SERVICE, LyricService, PRECACHE_SERVICE, PREFETCH_SERVICE};
+use crate::service::{block_service, search_override_service};
 use paste::paste;
 use std::fmt::Display;
 use tracing::debug;
@@ -83,15 +96,65 @@ async fn handle_setting(mut setting: SettingPayload) -> WebsocketResult {
         setSecondShow,
         getSecondShow,
         getLyricList,
+        searchLyric,
+        setSearchOverride,
+        getSearchOverrides,
+        deleteSearchOverride,
+        setArtistAlias,
+        getArtistAliases,
+        deleteArtistAlias,
+        previewLyric,
         setLyricSource,
+        assignLyric,
         getAllLyric,
+        editLyricLine,
+        insertLyricLine,
+        deleteLyricLines,
+        shiftLyricLines,
+        startTapSync,
+        tapSync,
+        undoTapSync,
+        getTapSync,
+        saveTapSync,
+        cancelTapSync,
+        getLyricInfo,
         setBlock,
         getBlockList,
         setUnblock,
+        addBlockRule,
+        getBlockRules,
+        deleteBlockRule,
         getCacheCount,
         setCacheClean,
+        getCacheList,
+        searchCache,
+        getCacheLyric,
+        deleteCache,
+        setCachePin,
+        getMissList,
+        setMissClean,
+        getMissRetry,
+        setMissRetry,
+        getCacheTtl,
+        setCacheTtl,
         getLyricOffset,
         setLyricOffset,
+        getOffsetList,
+        setGlobalOffset,
+        setSourceOffset,
+        getOffsetSetting,
+        setSyncAnchor,
+        setLyricScale,
+        clearLyricSync,
+        getLyricSync,
+        getSourceStatus,
+        setPrefetch,
+        getPrefetch,
+        startPrecache,
+        pausePrecache,
+        resumePrecache,
+        cancelPrecache,
+        getPrecacheStatus,
     };
     let mut result = match result {
         Ok(result) => result,
@@ -188,16 +251,78 @@ impl LyricSettingDatabaseKey {
 }
 
 async fn get_lyric_list(mut setting: SettingPayload) -> Result<WebsocketResult> {
-    let lyric_service = LYRIC_SERVICE.lock().await;
-    let data = lyric_service.get_search_result().await;
+    let (music_cache, sources) = {
+        let lyric_service = LYRIC_SERVICE.lock().await;
+        (lyric_service.music_cache(), lyric_service.sources())
+    };
+    let data = LyricService::get_search_result(music_cache, sources).await;
     setting.set_replay(data)?;
     Ok(WebsocketResult::Return(setting))
 }
 
+async fn search_lyric(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let request = setting.get_value::<SearchLyricRequest>()?;
+    let result = LyricService::search_by_keyword(&request).await?;
+    setting.set_replay(result)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_search_override(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let request = setting.get_value::<SearchOverrideRequest>()?;
+    setting.set_replay(search_override_service::set_override(request).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_search_overrides(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(search_override_service::get_overrides().await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn delete_search_override(setting: SettingPayload) -> Result<WebsocketResult> {
+    let id = setting.get_value::<i32>()?;
+    search_override_service::delete_override(id).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_artist_alias(setting: SettingPayload) -> Result<WebsocketResult> {
+    let alias = setting.get_value::<ArtistAlias>()?;
+    search_override_service::set_alias(alias).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_artist_aliases(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(search_override_service::get_aliases().await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn delete_artist_alias(setting: SettingPayload) -> Result<WebsocketResult> {
+    let artist = setting.get_value::<String>()?;
+    search_override_service::delete_alias(&artist).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn preview_lyric(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let key_data = setting.get_value::<SongInfoKey>()?;
+    let lyric = key_data.fetch_lyric().await?;
+    setting.set_replay(LyricPreview::from(&lyric))?;
+    Ok(WebsocketResult::Return(setting))
+}
+
 async fn set_lyric_source(setting: SettingPayload) -> Result<WebsocketResult> {
     let key_data = setting.get_value::<SongInfoKey>()?;
-    let mut lyric_service = LYRIC_SERVICE.lock().await;
-    lyric_service.set_song_by_key(&key_data).await?;
+    LyricService::set_song_by_key(&LYRIC_SERVICE, &key_data).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn assign_lyric(setting: SettingPayload) -> Result<WebsocketResult> {
+    let request = setting.get_value::<AssignLyricRequest>()?;
+    LyricService::assign_lyric(&LYRIC_SERVICE, &request).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_lyric_info(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.get_lyric_info().await?)?;
     Ok(WebsocketResult::Return(setting))
 }
 
@@ -210,9 +335,94 @@ async fn get_all_lyric(mut setting: SettingPayload) -> Result<WebsocketResult> {
     Ok(WebsocketResult::Return(setting))
 }
 
-async fn set_block(setting: SettingPayload) -> Result<WebsocketResult> {
+async fn edit_lyric_line(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let edit = setting.get_value::<LyricLineEdit>()?;
     let mut lyric_service = LYRIC_SERVICE.lock().await;
-    lyric_service.set_block(true).await?;
+    let lyrics = lyric_service
+        .edit_lyric(|lyric| lyric.update_line(edit.index, edit.time, edit.origin, edit.translation))
+        .await?;
+    setting.set_replay(lyrics)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn insert_lyric_line(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let insert = setting.get_value::<LyricLineInsert>()?;
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    let lyrics = lyric_service
+        .edit_lyric(|lyric| {
+            lyric.insert_line(insert.time, insert.origin, insert.translation)?;
+            Ok(())
+        })
+        .await?;
+    setting.set_replay(lyrics)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn delete_lyric_lines(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let range = setting.get_value::<LyricLineRange>()?;
+    let count = range.count.unwrap_or(1);
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    let lyrics = lyric_service
+        .edit_lyric(|lyric| lyric.remove_lines(range.start, count))
+        .await?;
+    setting.set_replay(lyrics)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn shift_lyric_lines(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let range = setting.get_value::<LyricLineRange>()?;
+    let count = range.count.unwrap_or(usize::MAX);
+    let seconds = range.offset as f32 / 1000f32;
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    let lyrics = lyric_service
+        .edit_lyric(|lyric| lyric.shift_lines(range.start, count, seconds))
+        .await?;
+    setting.set_replay(lyrics)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn start_tap_sync(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let request = setting.get_value::<TapSyncRequest>()?;
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.start_tap_sync(&request)?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn tap_sync(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.tap_sync()?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn undo_tap_sync(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.undo_tap_sync()?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_tap_sync(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.get_tap_sync())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn save_tap_sync(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.save_tap_sync().await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn cancel_tap_sync(setting: SettingPayload) -> Result<WebsocketResult> {
+    LYRIC_SERVICE.lock().await.cancel_tap_sync();
+    Ok(WebsocketResult::Return(setting))
+}
+
+/// 传 bid 时修改指定谱面, 否则修改当前歌曲
+async fn set_block(setting: SettingPayload) -> Result<WebsocketResult> {
+    match optional_bid(&setting)? {
+        Some(bid) => LyricService::set_block_by_bid(&LYRIC_SERVICE, bid, true).await?,
+        None => LyricService::set_block(&LYRIC_SERVICE, true).await?,
+    }
     Ok(WebsocketResult::Return(setting))
 }
 
@@ -224,8 +434,27 @@ async fn get_block_list(mut setting: SettingPayload) -> Result<WebsocketResult>
 }
 
 async fn set_unblock(setting: SettingPayload) -> Result<WebsocketResult> {
-    let mut lyric_service = LYRIC_SERVICE.lock().await;
-    lyric_service.set_block(false).await?;
+    match optional_bid(&setting)? {
+        Some(bid) => LyricService::set_block_by_bid(&LYRIC_SERVICE, bid, false).await?,
+        None => LyricService::set_block(&LYRIC_SERVICE, false).await?,
+    }
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn add_block_rule(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let request = setting.get_value::<BlockRuleRequest>()?;
+    setting.set_replay(block_service::add_rule(request).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_block_rules(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(block_service::get_rules().await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn delete_block_rule(setting: SettingPayload) -> Result<WebsocketResult> {
+    let id = setting.get_value::<i32>()?;
+    block_service::delete_rule(id).await?;
     Ok(WebsocketResult::Return(setting))
 }
 
@@ -236,23 +465,217 @@ async fn get_cache_count(mut setting: SettingPayload) -> Result<WebsocketResult>
     Ok(WebsocketResult::Return(setting))
 }
 
+/// 传 true 时同时清除固定的缓存
 async fn set_cache_clean(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let force = match setting.value {
+        Some(_) => setting.get_value::<Option<bool>>()?.unwrap_or(false),
+        None => false,
+    };
     let lyric_service = LYRIC_SERVICE.lock().await;
-    lyric_service.clear_all_cache().await;
+    lyric_service.clear_all_cache(force).await?;
     super::on_osu_state_change(OsuState::Clean).await;
     setting.set_replay("success")?;
     Ok(WebsocketResult::Return(setting))
 }
 
+async fn get_cache_list(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let query = match setting.value {
+        Some(_) => setting.get_value::<CacheQuery>()?,
+        None => CacheQuery::default(),
+    };
+    setting.set_replay(LyricService::find_cache(&query).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn search_cache(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let query = setting.get_value::<CacheQuery>()?;
+    setting.set_replay(LyricService::find_cache(&query).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_cache_lyric(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let bid = setting.get_value::<u32>()?;
+    setting.set_replay(LyricService::get_cache_lyric(bid).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn delete_cache(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let key = setting.get_value::<CacheKey>()?;
+    setting.set_replay(LyricService::delete_cache(&key).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_cache_pin(setting: SettingPayload) -> Result<WebsocketResult> {
+    let pin = setting.get_value::<CachePin>()?;
+    LyricService::set_cache_pin(&pin).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_miss_list(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(LyricService::get_miss_list().await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+/// 传 bid 时只清除这一张谱面
+async fn set_miss_clean(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let bid = match setting.value {
+        Some(_) => setting.get_value::<Option<i32>>()?,
+        None => None,
+    };
+    LyricService::clear_miss(bid).await?;
+    setting.set_replay("success")?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_miss_retry(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(LyricService::miss_retry())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_miss_retry(setting: SettingPayload) -> Result<WebsocketResult> {
+    let hours = setting.get_value::<u64>()?;
+    LyricService::set_miss_retry(hours).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_cache_ttl(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(LyricService::cache_ttl())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_cache_ttl(setting: SettingPayload) -> Result<WebsocketResult> {
+    let hours = setting.get_value::<u64>()?;
+    LyricService::set_cache_ttl(hours).await?;
+    Ok(WebsocketResult::Return(setting))
+}
+
 async fn get_lyric_offset(mut setting: SettingPayload) -> Result<WebsocketResult> {
     let lyric_service = LYRIC_SERVICE.lock().await;
-    setting.set_replay(lyric_service.get_offset())?;
+    setting.set_replay(lyric_service.get_lyric_offset())?;
     Ok(WebsocketResult::Return(setting))
 }
 
 async fn set_lyric_offset(setting: SettingPayload) -> Result<WebsocketResult> {
+    match setting.get_value::<OffsetValue>()? {
+        OffsetValue::Current(offset) => {
+            let mut lyric_service = LYRIC_SERVICE.lock().await;
+            lyric_service.set_offset(offset).await;
+        }
+        OffsetValue::Beatmap(request) => {
+            LyricService::set_offset_by_bid(&LYRIC_SERVICE, request.bid, request.offset).await?
+        }
+    }
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_offset_list(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let query = match setting.value {
+        Some(_) => setting.get_value::<OffsetQuery>()?,
+        None => OffsetQuery::default(),
+    };
+    setting.set_replay(LyricService::get_offset_list(&query).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_global_offset(mut setting: SettingPayload) -> Result<WebsocketResult> {
     let offset = setting.get_value::<i32>()?;
     let mut lyric_service = LYRIC_SERVICE.lock().await;
-    lyric_service.set_offset(offset).await;
+    setting.set_replay(lyric_service.set_global_offset(offset).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_source_offset(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let request = setting.get_value::<SourceOffsetRequest>()?;
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.set_source_offset(&request).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_offset_setting(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.get_offset_setting())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_sync_anchor(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let index = setting.get_value::<usize>()?;
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.set_sync_anchor(index).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn set_lyric_scale(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let scale = setting.get_value::<f64>()?;
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.set_lyric_scale(scale).await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn clear_lyric_sync(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let mut lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.clear_lyric_sync().await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_lyric_sync(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    let lyric_service = LYRIC_SERVICE.lock().await;
+    setting.set_replay(lyric_service.get_lyric_sync().await?)?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+fn optional_bid(setting: &SettingPayload) -> Result<Option<u32>> {
+    match setting.value {
+        Some(_) => setting.get_value::<Option<u32>>(),
+        None => Ok(None),
+    }
+}
+
+async fn set_prefetch(setting: SettingPayload) -> Result<WebsocketResult> {
+    let enabled = setting.get_value::<bool>()?;
+    PREFETCH_SERVICE.set_enabled(enabled).await?;
+    Ok(WebsocketResult::Broadcast(setting))
+}
+
+async fn get_prefetch(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(PREFETCH_SERVICE.is_enabled())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn start_precache(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    // 不传参数时使用默认值
+    let request = match setting.value {
+        Some(_) => setting.get_value_take::<PrecacheRequest>()?,
+        None => PrecacheRequest::default(),
+    };
+    PRECACHE_SERVICE.start(request).await?;
+    setting.set_replay(PRECACHE_SERVICE.status())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn pause_precache(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    PRECACHE_SERVICE.pause().await?;
+    setting.set_replay(PRECACHE_SERVICE.status())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn resume_precache(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    PRECACHE_SERVICE.resume().await?;
+    setting.set_replay(PRECACHE_SERVICE.status())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn cancel_precache(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    PRECACHE_SERVICE.cancel().await?;
+    setting.set_replay(PRECACHE_SERVICE.status())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_precache_status(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(PRECACHE_SERVICE.status())?;
+    Ok(WebsocketResult::Return(setting))
+}
+
+async fn get_source_status(mut setting: SettingPayload) -> Result<WebsocketResult> {
+    setting.set_replay(LyricService::get_source_status())?;
     Ok(WebsocketResult::Return(setting))
 }
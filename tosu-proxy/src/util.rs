@@ -69,6 +69,56 @@ pub(crate) async fn read_audio_length<P: AsRef<Path>>(file_path: P) -> Result<i3
     }
 }
 
+/// 音频文件内容的 SHA-256, 十六进制
+pub(crate) async fn hash_audio<P: AsRef<Path>>(file_path: P) -> Result<String> {
+    use sha2::{Digest, Sha256};
+    use tokio::io::AsyncReadExt;
+
+    let mut file = tokio::fs::File::open(file_path.as_ref()).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let len = file.read(&mut buf).await?;
+        if len == 0 {
+            break;
+        }
+        hasher.update(&buf[..len]);
+    }
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect())
+}
+
+/// 同 [`hash_audio`], 按路径和修改时间缓存结果, 文件没变时不再读取
+pub(crate) async fn hash_audio_cached<P: AsRef<Path>>(file_path: P) -> Result<String> {
+    use std::collections::HashMap;
+    use std::path::PathBuf;
+    use std::sync::{LazyLock, Mutex};
+    use std::time::SystemTime;
+
+    /// 超过后清空, 选歌界面划过的谱面不会无限增长
+    const MAX_CACHED: usize = 1024;
+    static CACHE: LazyLock<Mutex<HashMap<PathBuf, (SystemTime, String)>>> =
+        LazyLock::new(Default::default);
+
+    let path = file_path.as_ref();
+    let modified = tokio::fs::metadata(path).await?.modified()?;
+    if let Some((time, hash)) = CACHE.lock().unwrap().get(path)
+        && *time == modified
+    {
+        return Ok(hash.clone());
+    }
+    let hash = hash_audio(path).await?;
+    let mut cache = CACHE.lock().unwrap();
+    if cache.len() >= MAX_CACHED {
+        cache.clear();
+    }
+    cache.insert(path.to_path_buf(), (modified, hash.clone()));
+    Ok(hash)
+}
+
 pub(crate) fn to_json<'a, T: Deserialize<'a>>(value: &'a str) -> Result<T> {
     let mut de = serde_json::Deserializer::from_str(value);
     serde_path_to_error::deserialize(&mut de).map_err(|e| {
@@ -82,6 +132,8 @@ pub(crate) fn to_json<'a, T: Deserialize<'a>>(value: &'a str) -> Result<T> {
 #[cfg(test)]
 mod test {
     use crate::util::generate_random_string;
+    use crate::util::hash_audio;
+    use crate::util::hash_audio_cached;
     use crate::util::read_audio_length;
 
     #[tokio::test]
@@ -101,6 +153,46 @@ mod test {
         }
     }
 
+    #[tokio::test]
+    async fn test_hash_audio() -> crate::error::Result<()> {
+        let path = std::env::temp_dir().join(format!("tosu-proxy-hash-{}", std::process::id()));
+        std::fs::write(&path, "abc")?;
+        let hash = hash_audio(&path).await;
+        std::fs::remove_file(&path)?;
+        assert_eq!(
+            hash?,
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_hash_audio_cached() -> crate::error::Result<()> {
+        let path =
+            std::env::temp_dir().join(format!("tosu-proxy-hash-cached-{}", std::process::id()));
+        std::fs::write(&path, "abc")?;
+        let hash = hash_audio_cached(&path).await?;
+        let modified = std::fs::metadata(&path)?.modified()?;
+
+        // 修改时间不变时不重新读取
+        std::fs::write(&path, "abcd")?;
+        std::fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(modified)?;
+        assert_eq!(hash_audio_cached(&path).await?, hash);
+
+        let later = modified + std::time::Duration::from_secs(1);
+        std::fs::File::options()
+            .write(true)
+            .open(&path)?
+            .set_modified(later)?;
+        let changed = hash_audio_cached(&path).await;
+        std::fs::remove_file(&path)?;
+        assert_ne!(changed?, hash);
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_generate_random_string() {
         let random_string = generate_random_string();